@@ -0,0 +1,234 @@
+use chessbot::{parse_setoption, Engine, EngineOptions, GoCommand, InfoCallback, SearchInfo, SearchLimits, DEFAULT_HASH_MB, MAX_HASH_MB, MAX_THREADS};
+use cozy_chess::util::display_uci_move;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+// Milliseconds `bench` spends on each of its positions unless told otherwise
+const BENCH_MOVE_TIME: i32 = 1000;
+const BENCH_POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R w KQkq - 0 8",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 0 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "8/pp3pk1/2p3p1/4P3/3r4/2R3P1/PP3PKP/8 w - - 0 1",
+    "8/8/4kpp1/3p4/3P1PP1/4K3/8/8 w - - 0 1",
+];
+
+struct Uci {
+    engine: Arc<Mutex<Engine>>,
+    // The engine stays locked for the whole search, so it is stopped through this flag
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<()>>,
+}
+
+impl Uci {
+    fn new() -> Self {
+        let engine = Engine::default();
+        Uci {
+            stop: engine.stop_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            search: None,
+        }
+    }
+
+    // Returns false once the GUI asked us to quit
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&command) = tokens.first() else {
+            return true;
+        };
+
+        match command {
+            "uci" => {
+                println!("id name chessbot");
+                println!("id author chessbot developers");
+                println!("option name MoveTime type spin default 0 min 0 max 60000");
+                println!("option name Hash type spin default {} min 0 max {}", DEFAULT_HASH_MB, MAX_HASH_MB);
+                println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+                println!("option name Contempt type spin default 0 min -1000 max 1000");
+                println!("option name NullMove type check default true");
+                println!("option name ShallowPruning type check default true");
+                println!("option name Aspiration type check default true");
+                let defaults = EngineOptions::default();
+                for (name, default, min, max) in [
+                    ("LmrBase", defaults.lmr_base, 0, 500),
+                    ("LmrDivisor", defaults.lmr_divisor, 1, 1000),
+                    ("LmrMinDepth", defaults.lmr_min_depth, 0, 100),
+                    ("LmrMinMoves", defaults.lmr_min_moves, 0, 100),
+                    ("LmpBase", defaults.lmp_base, 0, 100),
+                    ("LmpMaxDepth", defaults.lmp_max_depth, 0, 100),
+                ] {
+                    println!("option name {} type spin default {} min {} max {}", name, default, min, max);
+                }
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop_search();
+                self.engine.lock().unwrap().start_new_game();
+            }
+            "position" => {
+                self.stop_search();
+                if let Err(e) = self.position(&tokens[1..]) {
+                    eprintln!("{}", e);
+                }
+            }
+            "go" => {
+                self.stop_search();
+                self.go(&tokens[1..]);
+            }
+            "stop" => self.stop_search(),
+            "bench" => {
+                self.stop_search();
+                self.bench(&tokens[1..]);
+            }
+            "setoption" => {
+                self.stop_search();
+                if let Err(e) = self.set_option(&tokens[1..]) {
+                    eprintln!("{}", e);
+                }
+            }
+            "quit" => {
+                self.stop_search();
+                return false;
+            }
+            _ => eprintln!("Unknown command: {}", line),
+        }
+        true
+    }
+
+    fn position(&mut self, args: &[&str]) -> Result<(), String> {
+        self.engine.lock().unwrap().set_position_uci(args)
+    }
+
+    fn set_option(&mut self, args: &[&str]) -> Result<(), String> {
+        let (name, value) = parse_setoption(args)?;
+        self.engine.lock().unwrap().apply_option(&name, &value)
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        let go = GoCommand::parse(args);
+        let infinite = go.infinite;
+
+        let engine = Arc::clone(&self.engine);
+        let (limits, root) = {
+            let engine = engine.lock().unwrap();
+            engine.clear_stop();
+            (go.limits(engine.board(), engine.options()), engine.board().clone())
+        };
+
+        let info: InfoCallback = Arc::new(move |info: &SearchInfo| {
+            let mut board = root.clone();
+            let mut pv = Vec::with_capacity(info.pv.len());
+            for &m in &info.pv {
+                pv.push(display_uci_move(&board, m).to_string());
+                board.play_unchecked(m);
+            }
+            println!(
+                "info depth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+                info.depth,
+                info.uci_score(),
+                info.nodes,
+                info.nps(),
+                info.hashfull,
+                info.elapsed.as_millis(),
+                pv.join(" ")
+            );
+            let _ = io::stdout().flush();
+        });
+
+        let stop = Arc::clone(&self.stop);
+        self.search = Some(thread::spawn(move || {
+            let engine = engine.lock().unwrap();
+            let best = engine.search(limits, Some(info));
+            // Even when the search has nothing left to do, `go infinite` only answers
+            // once it is told to stop
+            while infinite && !stop.load(Ordering::Relaxed) {
+                thread::park();
+            }
+            match best {
+                Some(m) => println!("bestmove {}", display_uci_move(engine.board(), m)),
+                None => println!("bestmove 0000"),
+            }
+            let _ = io::stdout().flush();
+        }));
+    }
+
+    // bench [movetime] [threads]: searches every bench position for `movetime` ms with 1,
+    // 2, 4... up to `threads` threads (all cores by default), starting from an empty table
+    // each time, and prints how fast and how deep each thread count got
+    fn bench(&self, args: &[&str]) {
+        let move_time = args.first().and_then(|v| v.parse().ok()).unwrap_or(BENCH_MOVE_TIME);
+        let max_threads = args
+            .get(1)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .clamp(1, MAX_THREADS);
+        let hash_mb = self.engine.lock().unwrap().options().hash_mb;
+
+        let mut threads = 1;
+        loop {
+            let mut engine = Engine::default();
+            let configured = engine.apply_option("Hash", &hash_mb.to_string()).and_then(|_| engine.apply_option("Threads", &threads.to_string()));
+            if let Err(e) = configured {
+                eprintln!("{}", e);
+                return;
+            }
+
+            let (mut nodes, mut depth, mut millis) = (0, 0, 0);
+            for fen in BENCH_POSITIONS {
+                engine.start_new_game();
+                if let Err(e) = engine.set_position(Some(fen)) {
+                    eprintln!("{}", e);
+                    return;
+                }
+                engine.clear_stop();
+                engine.search(SearchLimits::movetime(move_time), None);
+                if let Some(info) = engine.last_info() {
+                    nodes += info.nodes;
+                    depth += info.depth;
+                    millis += info.elapsed.as_millis() as u64;
+                }
+            }
+            println!(
+                "threads {} nodes {} nps {} average depth {:.1}",
+                threads,
+                nodes,
+                nodes * 1000 / millis.max(1),
+                depth as f64 / BENCH_POSITIONS.len() as f64
+            );
+            let _ = io::stdout().flush();
+
+            if threads >= max_threads {
+                break;
+            }
+            threads = (threads * 2).min(max_threads);
+        }
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            search.thread().unpark();
+            let _ = search.join();
+        }
+    }
+}
+
+fn main() {
+    let mut uci = Uci::new();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !uci.handle(line.trim()) {
+            break;
+        }
+        let _ = io::stdout().flush();
+    }
+    uci.stop_search();
+}
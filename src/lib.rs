@@ -1,29 +1,82 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+
 use pyo3::prelude::*;
-use rayon::prelude::*;
-use cozy_chess::{Board, Move, Color, Piece, Square, BitBoard};
+use cozy_chess::{Board, Move, Color, File, Piece, Rank, Square, BitBoard, get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves};
+use cozy_chess::util::{display_uci_move, parse_uci_move};
 use pyo3::exceptions::PyValueError;
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use rand::Rng;
-use std::thread;
 use std::time::{Duration, Instant};
 use lazy_static::lazy_static;
-use std::sync::atomic::{AtomicI32, AtomicBool, Ordering};
-use chess::Color::White;
+use std::sync::atomic::{AtomicI32, AtomicU16, AtomicU64, AtomicBool, Ordering};
 use pyo3::types::PyString;
-use std::str::FromStr;
-use pyo3::exceptions::socket::timeout;
-use pyo3::indoc::eprintdoc;
 
 
 lazy_static! {
     static ref ZOBRIST: Zobrist = Zobrist::new();
-    static ref TABLE: transposition_table = transposition_table::new();
+    // Stops a running `find_best_move` early
+    static ref STOP_SEARCH: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
 }
 
-static mut GAME_STAGE: i32 = 1;
 const PIECE_TYPES: usize = 12;
+// Pawn, knight, bishop, rook, queen, king, in centipawns. For SEE, move ordering and
+// pruning margins; the evaluation has its own middlegame and endgame values.
+const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+// How much each piece counts towards the game phase, in the same order
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+/// Game phase with every knight, bishop, rook and queen still on the board
+pub const MAX_PHASE: i32 = 24;
+/// Bigger than any score, search windows start at +-INFINITY
+pub const INFINITY: i32 = 32_000;
+/// Score for mating at the root. Being mated `ply` plies from the root scores
+/// `-MATE + ply`, and mating there `MATE - ply`.
+pub const MATE: i32 = 31_000;
+/// Anything further from zero than this is a mate score
+pub const MATE_BOUND: i32 = MATE - 1_000;
+// Extra room a capture gets in quiescence before delta pruning throws it away
+const DELTA_MARGIN: i32 = 2 * PIECE_VALUES[0];
+// Margins of the shallow depth pruning, per ply of depth left, and the deepest it goes
+const REVERSE_FUTILITY_MARGIN: i32 = PIECE_VALUES[0];
+const REVERSE_FUTILITY_DEPTH: i32 = 6;
+const FUTILITY_MARGIN: i32 = PIECE_VALUES[0];
+const FUTILITY_DEPTH: i32 = 3;
+const RAZOR_MARGIN: i32 = 3 * PIECE_VALUES[0];
+const RAZOR_DEPTH: i32 = 2;
 const BOARD_SQUARES: usize = 64;
+// Fixed so the Zobrist keys, and every hash built from them, never change between runs
+const ZOBRIST_SEED: u64 = 0x6368_6573_7362_6f74;
+// Moves assumed left in the game when a UCI `go` doesn't send `movestogo`
+const DEFAULT_MOVES_TO_GO: i32 = 20;
+// Milliseconds for a UCI `go` that gives no clock for the side to move and no other limit
+pub const DEFAULT_MOVE_TIME: i32 = 1000;
+// Half width of the first aspiration window, doubled on every re-search
+const ASPIRATION_WINDOW: i32 = 25;
+// Shallower iterations are too unstable to gain from aspiration windows
+const ASPIRATION_DEPTH: i32 = 4;
+// Depth left before null-move pruning is tried, and its smallest reduction
+const NULL_MOVE_MIN_DEPTH: i32 = 3;
+const NULL_MOVE_REDUCTION: i32 = 2;
+// From this depth on a null-move cutoff is verified with a normal search
+const NULL_MOVE_VERIFY_DEPTH: i32 = 8;
+// Depth left before the TT move is tested for being singular, how much shallower the
+// table entry may be, and how far below its score every other move has to stay, per ply
+const SINGULAR_DEPTH: i32 = 8;
+const SINGULAR_TT_DEPTH: i32 = 3;
+const SINGULAR_MARGIN: i32 = 2;
+// More legal moves than any position has, for per-move tables
+const MAX_MOVES: usize = 256;
+// Deeper than any search goes, for per-ply tables
+const MAX_PLY: usize = 128;
+// Bound on the history scores
+pub const MAX_HISTORY: i32 = 16_384;
+/// Most search threads the `Threads` option allows
+pub const MAX_THREADS: usize = 64;
+// Lazy SMP helper threads skip depths in blocks of `SKIP_SIZE` iterations, shifted by
+// `SKIP_PHASE`, so they spread over the next few depths instead of all searching the
+// same one as the main thread. Indexed by helper number, wrapping around.
+const SKIP_SIZE: [i32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [i32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+// More of one piece than either side can ever have
+const MAX_PIECE_COUNT: usize = 16;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Color2 {
@@ -54,635 +107,2680 @@ impl<'a> FromPyObject<'a> for Color2 {
 }
 
 #[pyfunction]
-fn update_FEN(fen: String, opp_move: String) -> PyResult<String> {
-    let mut board = match Board::from_fen(&fen, false) {
-        Ok(b) => b,
-        Err(_) => return Err(pyo3::exceptions::PyValueError::new_err("Rust: bad FEN string")),
-    };
+#[pyo3(name = "update_FEN")]
+fn update_fen(fen: String, opp_move: String) -> PyResult<String> {
+    play_uci_move(&fen, &opp_move).map_err(PyValueError::new_err)
+}
 
-    let parsed_move = opp_move.parse::<Move>();
-    if let Ok(m) = parsed_move {
-        if board.is_legal(m) {
-            board.play(m);
-            let updated_fen = board.to_string();
-            println!("fen: {}", updated_fen);
-            Ok(updated_fen)
-        } else {
-            Err(PyValueError::new_err("Rust: illegal move"))
-        }
-    } else {
-        Err(pyo3::exceptions::PyValueError::new_err("Rust: invalid move"))
+/// Plays the UCI move `uci_move` on `fen` and returns the FEN after it, what `update_FEN`
+/// does for Python. Castling is the king's two-square move, `e1g1`.
+pub fn play_uci_move(fen: &str, uci_move: &str) -> Result<String, String> {
+    let mut board = Board::from_fen(fen, false).map_err(|_| "Rust: bad FEN string".to_string())?;
+    let m = parse_uci_move(&board, uci_move.trim()).map_err(|_| "Rust: invalid move".to_string())?;
+    if !board.is_legal(m) {
+        return Err("Rust: illegal move".to_string());
     }
+    board.play_unchecked(m);
+    Ok(board.to_string())
 }
 
-static mut SEARCH: bool = false;
+#[pyfunction]
+fn stop() {
+    STOP_SEARCH.store(true, Ordering::Relaxed);
+}
 
-pub struct AlphaBeta;
+/// One search of one root position, shared by every thread working on it. Everything
+/// the threads change while searching is atomic.
+pub struct AlphaBeta {
+    tt: Arc<TranspositionTable>,
+    history: Arc<MoveHistory>,
+    // Raised from outside (`stop()`, UCI `stop`) to end the search early
+    stop: Arc<AtomicBool>,
+    stats: SearchStats,
+    start: Instant,
+    limits: SearchLimits,
+    options: EngineOptions,
+    // Late move reductions by depth and move number, see `EngineOptions::lmr_base`
+    reductions: Vec<[i32; MAX_MOVES]>,
+    pawns: Arc<PawnTable>,
+    // Set once the limits are hit; everything still running unwinds and its scores are junk
+    aborted: AtomicBool,
+    // There is nothing to fall back on before the first iteration finishes
+    can_abort: AtomicBool,
+}
 
+/// `moves` are the UCI moves played since `fen`, so repetitions of earlier positions
+/// are seen as draws. The move returned is for the position after them.
 #[pyfunction]
-fn find_best_move(fen: String, my_time: i32, game_on: bool, color_in: Color2) -> PyResult<String> {
+#[pyo3(signature = (fen, my_time, game_on, color_in, moves = None))]
+fn find_best_move(py: Python<'_>, fen: String, my_time: i32, game_on: bool, color_in: Color2, moves: Option<Vec<String>>) -> PyResult<String> {
     let color = match color_in {
         Color2::White => Color::White,
         Color2::Black => Color::Black,
     };
-    let mut board = match Board::from_fen(&fen, false) {
-        Ok(b) => Arc::new(Mutex::new(b)),
-        Err(_) => return Err(pyo3::exceptions::PyValueError::new_err("Rust: bad FEN string")),
-    };
-    let hash = ZOBRIST.hash_position(&board.lock().unwrap());
-    if let Some(entry) = TABLE.get(hash) {
-        println!("In table best move: {}", entry.best_move);
-        return Ok(entry.best_move.to_string());
+    let moves = moves.unwrap_or_default();
+    // Let another Python thread call `stop()` while this one searches
+    py.allow_threads(|| best_move_uci(&fen, my_time, game_on, color, &moves)).map_err(PyValueError::new_err)
+}
+
+/// What `find_best_move` does for Python: searches the position reached by playing
+/// `moves` from `fen` and returns the best move in UCI form, castling included, or `END`
+/// when there is no time or the game is already over.
+pub fn best_move_uci(fen: &str, my_time: i32, game_on: bool, color: Color, moves: &[String]) -> Result<String, String> {
+    let start = Board::from_fen(fen, false).map_err(|_| "Rust: bad FEN string".to_string())?;
+    let mut board = start.clone();
+    let mut history = Vec::new();
+    for mv in moves {
+        let m = parse_uci_move(&board, mv.trim()).map_err(|_| "Rust: invalid move".to_string())?;
+        if !board.is_legal(m) {
+            return Err("Rust: illegal move".to_string());
+        }
+        board.play_unchecked(m);
+        history.push(m);
     }
 
     if !game_on {
         return Ok("END".to_string());
     }
-    let eval = calculate_material(&board.lock().unwrap());
-    let time_limit = determine_time(eval, my_time, game_on, color);
-    println!("time limit {}", time_limit);
-    let best_move = AlphaBeta::start_alpha_beta_search(Arc::clone(&board), time_limit, game_on, color);
-    println!("Made it past alphas");
-
-    if time_limit == 0 {
-        return Ok("END".to_string());
+    let time_limit = determine_time(&board, my_time, game_on, color);
+    let tt = Arc::new(TranspositionTable::default());
+    STOP_SEARCH.store(false, Ordering::Relaxed);
+    let limits = SearchLimits::movetime(time_limit);
+    let position = Position::with_history(start, &history);
+    let best_move = AlphaBeta::start_alpha_beta_search(position, limits, game_on, EngineOptions::default(), tt, Arc::new(MoveHistory::default()), Arc::new(PawnTable::default()), Arc::clone(&STOP_SEARCH), None);
+
+    // No move either means no time or a game already over by mate or stalemate
+    match best_move {
+        Some(best_move) if time_limit != 0 => Ok(display_uci_move(&board, best_move).to_string()),
+        _ => Ok("END".to_string()),
     }
+}
 
-    Ok(best_move.to_string())
+/// What a search has to respect. Anything left at `None` is unlimited, so the default
+/// searches until stopped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    /// Milliseconds
+    pub time_limit: Option<i32>,
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
 }
 
-fn color_to_str(color:Color) -> &'static str {
-    match color {
-        Color::White => "white",
-        Color::Black=> "black",
+impl SearchLimits {
+    pub fn movetime(time_limit: i32) -> Self {
+        SearchLimits {
+            time_limit: Some(time_limit),
+            ..Default::default()
+        }
+    }
+
+    // `depth` is the depth about to be searched
+    fn reached(&self, start: Instant, depth: i32, nodes: u64) -> bool {
+        self.depth.is_some_and(|d| depth > d) || self.out_of_budget(start, nodes)
+    }
+
+    fn out_of_budget(&self, start: Instant, nodes: u64) -> bool {
+        self.time_limit.is_some_and(|t| start.elapsed().as_millis() >= t as u128)
+            || self.nodes.is_some_and(|n| nodes >= n)
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum MoveType {
-    PreviousBest,
-    Check,
-    Capture,
-    Normal,
+/// The limits of a UCI `go` command, before they are turned into `SearchLimits` for the
+/// side to move. Times are in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GoCommand {
+    pub wtime: Option<i32>,
+    pub btime: Option<i32>,
+    pub winc: i32,
+    pub binc: i32,
+    pub moves_to_go: Option<i32>,
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub move_time: Option<i32>,
+    pub infinite: bool,
 }
 
-impl AlphaBeta {
-    fn start_alpha_beta_search(board: Arc<Mutex<Board>>, time_limit: i32, game_on: bool, color: Color) -> Move {
-        let start = Instant::now();
-        let best_move = Arc::new(Mutex::new(None));
-        let fallback = Move {
-            from: cozy_chess::Square::A1,
-            to: cozy_chess::Square::A1,
-            promotion: None,
+impl GoCommand {
+    /// Reads the tokens after `go`. Unknown tokens and values that don't parse are skipped.
+    pub fn parse(args: &[&str]) -> Self {
+        let mut go = GoCommand::default();
+        let mut tokens = args.iter();
+        while let Some(&token) = tokens.next() {
+            let mut value = || tokens.next().and_then(|v| v.parse::<i64>().ok());
+            match token {
+                "wtime" => go.wtime = value().map(|v| v as i32),
+                "btime" => go.btime = value().map(|v| v as i32),
+                "winc" => go.winc = value().unwrap_or(0) as i32,
+                "binc" => go.binc = value().unwrap_or(0) as i32,
+                "movestogo" => go.moves_to_go = value().map(|v| v as i32),
+                "depth" => go.depth = value().map(|v| v as i32),
+                "nodes" => go.nodes = value().map(|v| v as u64),
+                "movetime" => go.move_time = value().map(|v| v as i32),
+                "infinite" => go.infinite = true,
+                _ => {}
+            }
+        }
+        go
+    }
+
+    /// What to search `board` with. `movetime` wins, then the `MoveTime` option, then our
+    /// clock. Without any of them, or a depth or node limit, the move gets
+    /// `DEFAULT_MOVE_TIME`, so only `go infinite` searches until stopped.
+    pub fn limits(&self, board: &Board, options: &EngineOptions) -> SearchLimits {
+        let mut limits = SearchLimits {
+            time_limit: self.move_time,
+            depth: self.depth,
+            nodes: self.nodes,
         };
+        if self.infinite || limits.time_limit.is_some() {
+            return limits;
+        }
 
-        // Spawn thread for continuous search
-        thread::spawn({
-            println!("start alpha spawned thread!");
-            let board = Arc::clone(&board);
-            let best_move = Arc::clone(&best_move);
-            let table = transposition_table::new();
-            let mut best_score = -i32::MAX;
-            move || {
-                let mut depth = 1;
-                let mut alpha = -i32::MAX;
-                let mut beta = i32::MAX;
-
-                while start.elapsed().as_millis() < time_limit as u128 {
-                    // Get a fresh snapshot of the board state each iteration
-                    let current_board = {
-                        let board_locked = board.lock().unwrap();
-                        board_locked.clone()
-                    };
-
-                    let candidate_move = AlphaBeta::iterative_deepening(
-                        &current_board,
-                        depth,
-                        start,
-                        time_limit,
-                        color,
-                        &table,
-                    );
-                    println!("Depth {} candidate: {}", depth, candidate_move);
-
-                    {
-                        // Update the shared board state with the candidate move.
-                        let mut board_locked = board.lock().unwrap();
-                        board_locked.play_unchecked(candidate_move);
-                    }
-                    // Update the shared best move
-                    *best_move.lock().unwrap() = Some(candidate_move);
+        let color = board.side_to_move();
+        let (my_time, my_inc) = match color {
+            Color::White => (self.wtime, self.winc),
+            Color::Black => (self.btime, self.binc),
+        };
+        if options.move_time > 0 {
+            limits.time_limit = Some(options.move_time);
+        } else if let Some(my_time) = my_time {
+            // Never plan to spend more than our share of the clock
+            let budget = determine_time(board, my_time, true, color);
+            let share = my_time / self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1) + my_inc;
+            limits.time_limit = Some(budget.min(share).max(1));
+        } else if limits.depth.is_none() && limits.nodes.is_none() {
+            limits.time_limit = Some(DEFAULT_MOVE_TIME);
+        }
+        limits
+    }
+}
 
-                    depth += 1; // Increase depth for the next iteration
-                    if !game_on {
-                        println!("Game over detected. Exiting search loop.");
-                        break;
-                    }
-                }
-            }
-        });
+/// Progress of a search, reported after every completed depth.
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: i32,
+    pub score: i32,
+    /// All nodes searched, quiescence nodes included
+    pub nodes: u64,
+    pub qnodes: u64,
+    /// Times the score fell below or rose above the aspiration window so far, each one
+    /// costing a re-search
+    pub fail_lows: u32,
+    pub fail_highs: u32,
+    /// Permille of the transposition table in use
+    pub hashfull: u32,
+    pub elapsed: Duration,
+    pub pv: Vec<Move>,
+}
 
-        while start.elapsed().as_millis() < time_limit as u128 && game_on {
-            println!("start alpha start while loop happened");
-            if let Some(best) = best_move.lock().unwrap().clone() {
-                println!("best in while loop in start alpha {}", best);;
-                return best;
-            }
+impl SearchInfo {
+    pub fn nps(&self) -> u64 {
+        let millis = self.elapsed.as_millis().max(1) as u64;
+        self.nodes * 1000 / millis
+    }
 
-            std::thread::sleep(std::time::Duration::from_millis(5));
+    /// Moves until mate when the score is a forced mate: positive when the side to move
+    /// mates, negative when it gets mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score >= MATE_BOUND {
+            Some((MATE - self.score + 1) / 2)
+        } else if self.score <= -MATE_BOUND {
+            Some(-(MATE + self.score) / 2)
+        } else {
+            None
         }
+    }
 
-        let best = best_move.lock().unwrap().unwrap_or(fallback);
-        println!("end best move{}", best);
-        best
+    /// The score the way UCI `info` prints it, `cp <score>` or `mate <moves>`.
+    pub fn uci_score(&self) -> String {
+        match self.mate_in() {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", self.score),
+        }
     }
+}
 
-    fn categorize_moves(board: &Board, previous_best: Option<Move>, color: Color) -> Vec<Move> {
-        let mut total = Vec::new();
-        //println!("categorize color {}", color);
-        board.generate_moves(|moves| {
-            total.extend(moves);
-            false
-        });
-        let mut best_move = previous_best;
-        let mut checks = Vec::new();
-        let mut captures = Vec::new();
-        let mut normal = Vec::new();
+/// Node counters shared by every thread working on one search.
+#[derive(Default)]
+pub struct SearchStats {
+    pub nodes: AtomicU64,
+    pub qnodes: AtomicU64,
+}
 
-        if let Some(entry) = TABLE.get(ZOBRIST.hash_position(&board)) {
-            if let Some(m) = Move::from_str(&entry.best_move).ok() {
-                best_move = Some(m);
-            }
-        }
+impl SearchStats {
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed) + self.qnodes.load(Ordering::Relaxed)
+    }
+}
 
-        let mut sorted_moves = Vec::new();
+pub type InfoCallback = Arc<dyn Fn(&SearchInfo) + Send + Sync>;
+
+/// Engine options that can be changed between searches, by name, the same way a UCI
+/// `setoption` would.
+#[derive(Clone, Debug)]
+pub struct EngineOptions {
+    /// Fixed time per move in milliseconds. `0` lets `determine_time` decide.
+    pub move_time: i32,
+    /// Transposition table size in MB, 0 for no table
+    pub hash_mb: usize,
+    /// How much worse than equal a draw is for the side searching, in eval units.
+    /// Negative values make the engine look for draws.
+    pub contempt: i32,
+    /// Search threads. The first one owns the result, the others share the transposition
+    /// table with it and only help by filling it.
+    pub threads: usize,
+    /// Null-move pruning, off only to compare against or debug
+    pub null_move: bool,
+    /// Reverse futility pruning, futility pruning and razoring, same as `null_move`
+    pub shallow_pruning: bool,
+    /// Aspiration windows from `ASPIRATION_DEPTH` on, same as `null_move`
+    pub aspiration: bool,
+    /// Late move reductions are `(lmr_base + ln(depth) * ln(move number) / lmr_divisor)`
+    /// plies, both in hundredths
+    pub lmr_base: i32,
+    pub lmr_divisor: i32,
+    /// Depth left and moves searched before late moves are reduced
+    pub lmr_min_depth: i32,
+    pub lmr_min_moves: i32,
+    /// Late move pruning skips the remaining quiet moves once `lmp_base + depth * depth`
+    /// have been searched, at depths up to `lmp_max_depth` (0 turns it off)
+    pub lmp_base: i32,
+    pub lmp_max_depth: i32,
+}
 
-        for m in total {
-            let mut new_board = board.clone();
-            new_board.play_unchecked(m);
-            let captured = new_board.piece_on(m.to);
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            move_time: 0,
+            hash_mb: DEFAULT_HASH_MB,
+            contempt: 0,
+            threads: 1,
+            null_move: true,
+            shallow_pruning: true,
+            aspiration: true,
+            lmr_base: 75,
+            lmr_divisor: 225,
+            lmr_min_depth: 3,
+            lmr_min_moves: 3,
+            lmp_base: 3,
+            lmp_max_depth: 3,
+        }
+    }
+}
 
-            if Some(m) == best_move {
-                sorted_moves.push(m);
-            } else if new_board.checkers() != BitBoard::EMPTY {
-                checks.push(m);
-            } else if captured.is_some() {
-                captures.push(m);
-            } else {
-                normal.push(m);
-            }
+impl EngineOptions {
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_ascii_lowercase().as_str() {
+            "movetime" => self.move_time = parse_option(name, value)?,
+            "hash" => self.hash_mb = parse_option::<usize>(name, value)?.min(MAX_HASH_MB),
+            "contempt" => self.contempt = parse_option(name, value)?,
+            "threads" => self.threads = parse_option::<usize>(name, value)?.clamp(1, MAX_THREADS),
+            "nullmove" => self.null_move = parse_option(name, value)?,
+            "shallowpruning" => self.shallow_pruning = parse_option(name, value)?,
+            "aspiration" => self.aspiration = parse_option(name, value)?,
+            "lmrbase" => self.lmr_base = parse_option(name, value)?,
+            "lmrdivisor" => self.lmr_divisor = parse_option::<i32>(name, value)?.max(1),
+            "lmrmindepth" => self.lmr_min_depth = parse_option(name, value)?,
+            "lmrminmoves" => self.lmr_min_moves = parse_option(name, value)?,
+            "lmpbase" => self.lmp_base = parse_option(name, value)?,
+            "lmpmaxdepth" => self.lmp_max_depth = parse_option(name, value)?,
+            _ => return Err(format!("Rust: unknown option {}", name)),
         }
-        if let Some(bm) = best_move {
-            sorted_moves.push(bm);
+        Ok(())
+    }
+}
+
+fn parse_option<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("Rust: bad value for {}: {}", name, value))
+}
+
+/// Splits the tokens after UCI `setoption` into the option name and its value. Both may
+/// contain spaces: `name Move Overhead value 30` is `("Move Overhead", "30")`.
+pub fn parse_setoption(args: &[&str]) -> Result<(String, String), String> {
+    let value_at = args.iter().position(|&t| t == "value").unwrap_or(args.len());
+    if args.first() != Some(&"name") || value_at < 2 {
+        return Err(format!("Bad setoption command: {}", args.join(" ")));
+    }
+    let name = args[1..value_at].join(" ");
+    let value = args.get(value_at + 1..).map(|v| v.join(" ")).unwrap_or_default();
+    Ok((name, value))
+}
+
+/// A long-lived engine for one game at a time.
+///
+/// Unlike `find_best_move`, the board, the moves played so far, the transposition table
+/// and the pawn table are kept between calls to `go`, so search knowledge from the
+/// previous move is reused instead of thrown away.
+#[pyclass]
+pub struct Engine {
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,
+    tt: Arc<TranspositionTable>,
+    history: Arc<MoveHistory>,
+    pawns: Arc<PawnTable>,
+    stop: Arc<AtomicBool>,
+    options: EngineOptions,
+    last_info: Arc<Mutex<Option<SearchInfo>>>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine {
+            start: Board::default(),
+            board: Board::default(),
+            moves: Vec::new(),
+            tt: Arc::new(TranspositionTable::default()),
+            history: Arc::new(MoveHistory::default()),
+            pawns: Arc::new(PawnTable::default()),
+            stop: Arc::new(AtomicBool::new(false)),
+            options: EngineOptions::default(),
+            last_info: Arc::new(Mutex::new(None)),
         }
-        sorted_moves.extend(checks);
-        sorted_moves.extend(captures);
-        sorted_moves.extend(normal);
-        // println!("Total moves:");
-        // for move_ in &sorted_moves {
-        //     println!("{}", move_);
-        // }
+    }
+}
 
-        sorted_moves
+impl Engine {
+    pub fn board(&self) -> &Board {
+        &self.board
     }
 
-    fn iterative_deepening(board: &Board, depth: i32, start: Instant, limit: i32, color: Color, tt: &transposition_table) -> Move {
-        let fallback = Move {
-            from: cozy_chess::Square::A1,
-            to: cozy_chess::Square::A1,
-            promotion: None,
-        };
-        // let current_color = if depth > 1 {
-        //     if color == Color::White { Color::Black } else { Color::White }
-        // } else {
-        //     color
-        // };
-        //println!("iterative deepening color: {}", color);
-
-        let mut best_move = Arc::new(Mutex::new(None));
-        let previous_best = best_move.lock().unwrap().clone();
-        let moves = Self::categorize_moves(&board, previous_best, color);
-        let mut max_eval = Arc::new(AtomicI32::new(-i32::MAX));
-        let mut root_move = Arc::new(Mutex::new(None));
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
 
-        if moves.is_empty() {
-            return fallback;
-        }
-        let root_clone = Arc::clone(&root_move);
-        moves.par_iter().for_each(|m| {
-            let mut new_board = board.clone();
-            if new_board.is_legal(*m) {
-                new_board.play_unchecked(*m);
-
-                let eval = Self::alpha_beta_search(&new_board, depth - 1, -i32::MAX, i32::MAX, false, previous_best, color, tt);
-
-                let mut best_move_lock = best_move.lock().unwrap();
-                let current_max = max_eval.load(Ordering::Relaxed);
-                if eval > current_max {
-                    max_eval.store(eval, Ordering::Relaxed);
-                    *best_move_lock = Some(*m);
-                    let mut root_move_lock = root_clone.lock().unwrap();
-                    *root_move_lock = Some(*m);
-                }
-            }
-        });
+    pub fn options(&self) -> &EngineOptions {
+        &self.options
+    }
 
-        if start.elapsed().as_millis() < limit as u128 {
-            return root_move.lock().unwrap().unwrap_or(moves[0]);
+    /// Changes an option by name, like UCI `setoption`, resizing the transposition table
+    /// when `Hash` changes.
+    pub fn apply_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.options.set(name, value)?;
+        if self.options.hash_mb != self.tt.size_mb() {
+            self.tt = Arc::new(TranspositionTable::new(self.options.hash_mb));
         }
-
-        root_move.lock().unwrap().unwrap_or(moves[0])
+        Ok(())
     }
 
-    fn alpha_beta_search(board: &Board, depth: i32, mut alpha: i32, mut beta: i32, max_player: bool, previous_best: Option<Move>, color: Color, tt: &transposition_table) -> i32 {
-        //println!("SEARCHING...");
-        let hash = board.hash();
+    /// Sets up a new position, keeping the transposition table.
+    pub fn set_position(&mut self, fen: Option<&str>) -> Result<(), String> {
+        let board = match fen {
+            Some(fen) => Board::from_fen(fen, false).map_err(|_| "Rust: bad FEN string".to_string())?,
+            None => Board::default(),
+        };
+        self.start = board.clone();
+        self.board = board;
+        self.moves.clear();
+        Ok(())
+    }
 
-        if let Some(entry) = tt.get(hash) {
-            if entry.depth >= depth {
-                match entry.flag {
-                    flag_type::Exact => return entry.score,
-                    flag_type::Lower => if entry.score > alpha { alpha = entry.score },
-                    flag_type::Upper => if entry.score < beta { beta = entry.score },
-                }
-                if beta <= alpha {
-                    return entry.score;
-                }
-            }
+    /// Plays a move in UCI notation. Both `e1g1` and `e1h1` style castling are accepted.
+    pub fn play_move(&mut self, mv: &str) -> Result<Move, String> {
+        let m = parse_uci_move(&self.board, mv.trim()).map_err(|_| "Rust: invalid move".to_string())?;
+        if !self.board.is_legal(m) {
+            return Err("Rust: illegal move".to_string());
         }
+        self.board.play_unchecked(m);
+        self.moves.push(m);
+        Ok(m)
+    }
 
-        if depth == 0 {
-            match calculate_material(&board) {
-                Ok((white_pts, black_pts)) => {
-                    if color == Color::White {
-                        return white_pts;
-                    } else {
-                        return black_pts;
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error in SEARCH {}", e);
-                    return 0;
-                }
-            }
+    /// Sets up the position of a UCI `position` command from the tokens after it:
+    /// `startpos` or `fen <fen>`, optionally followed by `moves <move>...`.
+    pub fn set_position_uci(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves_at = args.iter().position(|&t| t == "moves").unwrap_or(args.len());
+        match args.first() {
+            Some(&"startpos") if moves_at == 1 => self.set_position(None)?,
+            Some(&"fen") => self.set_position(Some(&args[1..moves_at].join(" ")))?,
+            _ => return Err(format!("Bad position command: {}", args.join(" "))),
         }
+        for mv in args.iter().skip(moves_at + 1) {
+            self.play_move(mv)?;
+        }
+        Ok(())
+    }
 
-        //println!("Alpha Search Color {}", color);
-        let moves = Self::categorize_moves(&board, previous_best, color);
+    /// Starts a new game from the initial position and forgets all search knowledge.
+    pub fn start_new_game(&mut self) {
+        self.tt.clear();
+        self.history.clear();
+        self.start = Board::default();
+        self.board = Board::default();
+        self.moves.clear();
+    }
 
-        if max_player {
-            let mut max_eval = -i32::MAX;
-            for m in moves {
-                let mut new_board = board.clone();
-                if new_board.is_legal(m) {
-                    new_board.play_unchecked(m);
-                    let opponent_color = if color == Color::White { Color::Black } else { Color::White };
-                    let eval = Self::alpha_beta_search(&new_board, depth - 1, alpha, beta, false, previous_best, opponent_color, tt);
-                    max_eval = max_eval.max(eval);
-                    alpha = alpha.max(eval);
-                    if beta <= alpha {
-                        break;
-                    }
-                }
+    /// Searches the current position with a time budget from `determine_time` (or the
+    /// `MoveTime` option) and returns the chosen move, or `None` when the game is not on
+    /// or the side to move has no legal moves.
+    pub fn think(&self, my_time: i32, game_on: bool) -> Option<Move> {
+        if !game_on {
+            return None;
+        }
+        let time_limit = if self.options.move_time > 0 {
+            self.options.move_time
+        } else {
+            determine_time(&self.board, my_time, game_on, self.board.side_to_move())
+        };
+        if time_limit == 0 {
+            return None;
+        }
+        self.clear_stop();
+        self.search(SearchLimits::movetime(time_limit), None)
+    }
+
+    /// Searches the current position until `limits` are reached or `request_stop` is
+    /// called. `info` is called after every completed depth. The stop flag is not cleared
+    /// here, see `clear_stop`.
+    pub fn search(&self, limits: SearchLimits, info: Option<InfoCallback>) -> Option<Move> {
+        *self.last_info.lock().unwrap() = None;
+        let last_info = Arc::clone(&self.last_info);
+        let report: InfoCallback = Arc::new(move |search_info: &SearchInfo| {
+            *last_info.lock().unwrap() = Some(search_info.clone());
+            if let Some(info) = &info {
+                info(search_info);
             }
+        });
 
-            tt.store(hash, Entry {
-                score: max_eval,
-                depth: depth as i32,
-                flag: flag_type::Exact,
-                best_move: "BestMove".to_string(),
-            });
+        let position = Position::with_history(self.start.clone(), &self.moves);
+        AlphaBeta::start_alpha_beta_search(position, limits, true, self.options.clone(), Arc::clone(&self.tt), Arc::clone(&self.history), Arc::clone(&self.pawns), Arc::clone(&self.stop), Some(report))
+    }
 
-            max_eval
-        } else {
-            let mut min_eval = i32::MAX;
-            for m in moves {
-                let mut new_board = board.clone();
-                if new_board.is_legal(m) {
-                    new_board.play_unchecked(m);
-                    let opponent_color = if color == Color::White { Color::Black } else { Color::White };
-                    let eval = Self::alpha_beta_search(&new_board, depth - 1, alpha, beta, true, previous_best, opponent_color, tt);
-                    min_eval = min_eval.min(eval);
-                    beta = beta.min(eval);
-                    if beta <= alpha {
-                        break;
-                    }
-                }
-            }
+    /// What the last search found at the deepest depth it completed.
+    pub fn last_info(&self) -> Option<SearchInfo> {
+        self.last_info.lock().unwrap().clone()
+    }
 
-            tt.store(hash, Entry {
-                score: min_eval,
-                depth: depth as i32,
-                flag: flag_type::Exact,
-                best_move: "BestMove".to_string(),
-            });
+    /// Asks a running search to return as soon as possible.
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
 
-            min_eval
-        }
+    /// Re-arms the stop flag before starting a search. Callers that search on another
+    /// thread should do this before spawning it, so an early `request_stop` isn't lost.
+    pub fn clear_stop(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+    }
+
+    /// The flag behind `request_stop`, for stopping a search running on another thread.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
     }
 }
 
-fn determine_time(eval: Result<(i32, i32), String>, time: i32, game_on: bool, mycolor: Color) -> i32 {
-    if !game_on {
-        return 0;
+#[pymethods]
+impl Engine {
+    #[new]
+    #[pyo3(signature = (fen = None))]
+    fn py_new(fen: Option<String>) -> PyResult<Self> {
+        let mut engine = Engine::default();
+        engine.set_position(fen.as_deref()).map_err(PyValueError::new_err)?;
+        Ok(engine)
     }
 
-    if time < 10_000 {
-        return 200;
+    /// Plays a move (UCI notation) on the engine's board.
+    fn push_move(&mut self, mv: String) -> PyResult<()> {
+        self.play_move(&mv).map_err(PyValueError::new_err)?;
+        Ok(())
     }
 
-    match eval {
-        Ok((white_pts, black_pts)) => {
-            if mycolor == Color::White {
-                if white_pts < black_pts {
-                    return 750;
-                }
-            } else {
-                if black_pts < white_pts {
-                    return 750;
-                }
-            }
+    /// Searches the current position. Returns the best move in UCI notation, or "END"
+    /// when there is nothing to search.
+    #[pyo3(signature = (my_time, game_on = true))]
+    fn go(&self, py: Python<'_>, my_time: i32, game_on: bool) -> String {
+        let best = py.allow_threads(|| self.think(my_time, game_on));
+        match best {
+            Some(m) => display_uci_move(&self.board, m).to_string(),
+            None => "END".to_string(),
         }
-        Err(_) => {
-            return 500;
+    }
+
+    fn stop(&self) {
+        self.request_stop();
+    }
+
+    /// Starts over from `fen` (or the initial position) keeping what has been learned,
+    /// then plays `moves` (UCI notation) so repetitions of them are known.
+    #[pyo3(signature = (fen = None, moves = None))]
+    fn reset(&mut self, fen: Option<String>, moves: Option<Vec<String>>) -> PyResult<()> {
+        self.set_position(fen.as_deref()).map_err(PyValueError::new_err)?;
+        for mv in moves.unwrap_or_default() {
+            self.play_move(&mv).map_err(PyValueError::new_err)?;
         }
+        Ok(())
     }
 
-    if unsafe { GAME_STAGE == 3 } {
-        return 750;
+    /// Starts a new game from the initial position and forgets all search knowledge.
+    fn new_game(&mut self) {
+        self.start_new_game();
     }
 
-    500
+    fn set_option(&mut self, name: String, value: String) -> PyResult<()> {
+        self.apply_option(&name, &value).map_err(PyValueError::new_err)
+    }
+
+    fn fen(&self) -> String {
+        self.board.to_string()
+    }
+
+    /// Score of the last `go` as "cp <score>" or "mate <moves>" (negative when getting
+    /// mated), or None before any search.
+    fn score(&self) -> Option<String> {
+        self.last_info().map(|info| info.uci_score())
+    }
+
+    fn start_fen(&self) -> String {
+        self.start.to_string()
+    }
+
+    fn move_history(&self) -> Vec<String> {
+        let mut board = self.start.clone();
+        let mut history = Vec::with_capacity(self.moves.len());
+        for &m in &self.moves {
+            history.push(display_uci_move(&board, m).to_string());
+            board.play_unchecked(m);
+        }
+        history
+    }
 }
 
-pub struct Zobrist {
-    pub piece_keys: [[u64; BOARD_SQUARES]; PIECE_TYPES],
-    pub side_to_move_key: u64,
+// Move ordering buckets, searched in this order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MoveType {
+    PreviousBest,
+    // Captures that don't lose material by SEE
+    Capture,
+    Promotion,
+    Killer,
+    Countermove,
+    // Ordered by history score
+    Normal,
+    LosingCapture,
 }
 
-impl Zobrist {
-    pub fn new() -> Self {
-        let mut rng = rand::rng();
-        let mut piece_keys = [[0u64; BOARD_SQUARES]; PIECE_TYPES];
+impl AlphaBeta {
+    fn new(tt: Arc<TranspositionTable>, history: Arc<MoveHistory>, pawns: Arc<PawnTable>, limits: SearchLimits, options: EngineOptions, stop: Arc<AtomicBool>) -> Self {
+        AlphaBeta {
+            tt,
+            history,
+            pawns,
+            stop,
+            stats: SearchStats::default(),
+            start: Instant::now(),
+            limits,
+            reductions: Self::reduction_table(&options),
+            options,
+            aborted: AtomicBool::new(false),
+            can_abort: AtomicBool::new(false),
+        }
+    }
 
-        for piece in 0.. PIECE_TYPES {
-            for square in 0..BOARD_SQUARES {
-                piece_keys[piece][square] = rng.random();
+    /// Runs a whole search, Lazy SMP style: the calling thread is the main thread and
+    /// `options.threads - 1` helpers search the same root next to it, sharing the
+    /// transposition and pawn tables. The helpers are joined before this returns, so no
+    /// search work is left behind. `stop` ends it early from the outside. `None` means the
+    /// side to move is mated or stalemated.
+    #[allow(clippy::too_many_arguments)]
+    fn start_alpha_beta_search(position: Position, limits: SearchLimits, game_on: bool, options: EngineOptions, tt: Arc<TranspositionTable>, history: Arc<MoveHistory>, pawns: Arc<PawnTable>, stop: Arc<AtomicBool>, info: Option<InfoCallback>) -> Option<Move> {
+        tt.new_search();
+        history.new_search();
+        let search = AlphaBeta::new(tt, history, pawns, limits, options, stop);
+        std::thread::scope(|scope| {
+            for thread in 1..search.options.threads {
+                let (search, position) = (&search, position.clone());
+                scope.spawn(move || search.iterative_deepening(&position, game_on, None, thread));
             }
-        }
+            let best = search.iterative_deepening(&position, game_on, info.as_ref(), 0);
+            // Whatever the helpers are still searching can't change the result any more
+            search.aborted.store(true, Ordering::Relaxed);
+            best
+        })
+    }
 
-        Zobrist {
-            piece_keys,
-            side_to_move_key: rng.random(),
+    // Whether helper `thread` leaves `depth` to the others, see `SKIP_SIZE`
+    fn skips_depth(thread: usize, depth: i32) -> bool {
+        if thread == 0 {
+            return false;
         }
+        let index = (thread - 1) % SKIP_SIZE.len();
+        ((depth + SKIP_PHASE[index]) / SKIP_SIZE[index]) % 2 != 0
     }
 
-    pub fn hash_position(&self, board: &Board) -> u64 {
-        let mut  hash: u64 = 0;
-
-        for square_index in 0..64 {
-            if let Some(square) = Square::try_index(square_index) {
-                if let Some(piece) = board.piece_on(square) {
-                    // Ensure piece_index is valid
-                    let piece_index = match piece {
-                        Piece::Pawn => 0,
-                        Piece::Knight => 1,
-                        Piece::Bishop => 2,
-                        Piece::Rook => 3,
-                        Piece::Queen => 4,
-                        Piece::King => 5,
-                        _ => continue,
-                    };
-
-                    if piece_index >= PIECE_TYPES || square_index >= BOARD_SQUARES {
-                        eprintln!("Piece_index or square_index out of bounds");
-                        continue;
-                    }
-
-                    hash ^= self.piece_keys[piece_index][square_index];
+    /// Searches the same root at depth 1, 2, 3... until a limit is hit or the search is
+    /// stopped, and returns the best move of the last iteration that ran to completion.
+    /// Each iteration searches the previous PV first; one cut short is thrown away.
+    ///
+    /// From `ASPIRATION_DEPTH` on, an iteration starts with a narrow window around the
+    /// previous score, which is widened and searched again whenever the score falls
+    /// outside of it.
+    ///
+    /// `thread` 0 is the main thread, the only one that reports progress and whose
+    /// first iteration has to finish. The helpers skip some depths, see `skips_depth`.
+    fn iterative_deepening(&self, position: &Position, game_on: bool, info: Option<&InfoCallback>, thread: usize) -> Option<Move> {
+        let mut pv: Vec<Move> = Vec::new();
+        let mut score: i32 = 0;
+        let mut depth = 1;
+        let (mut fail_lows, mut fail_highs) = (0, 0);
+
+        // Depth 1 always runs on the main thread so there is a move to return. Past
+        // `MAX_PLY` there is nothing left to find, even when the position is trivial.
+        'deepening: while (depth == 1 && thread == 0)
+            || (depth < MAX_PLY as i32 && !self.limits.reached(self.start, depth, self.stats.total_nodes()) && !self.stop.load(Ordering::Relaxed)) {
+            if Self::skips_depth(thread, depth) {
+                depth += 1;
+                continue;
+            }
+            let mut delta = ASPIRATION_WINDOW;
+            let (mut alpha, mut beta) = if self.options.aspiration && depth >= ASPIRATION_DEPTH && score.abs() < MATE_BOUND {
+                (score - delta, score + delta)
+            } else {
+                (-INFINITY, INFINITY)
+            };
+
+            loop {
+                let Some((eval, line)) = self.search_root(position, depth, alpha, beta, &pv) else {
+                    break 'deepening;
+                };
+                if eval <= alpha && alpha > -INFINITY {
+                    // Fail low: nothing was found in the window, look lower
+                    fail_lows += 1;
+                    beta = (alpha + beta) / 2;
+                    alpha = (eval - delta).max(-INFINITY);
+                } else if eval >= beta && beta < INFINITY {
+                    // Fail high: the move that did it is worth searching first again
+                    fail_highs += 1;
+                    beta = (eval + delta).min(INFINITY);
+                    pv = line;
+                } else {
+                    score = eval;
+                    pv = line;
+                    break;
                 }
+                delta *= 2;
+            }
+
+            if thread == 0 {
+                self.can_abort.store(true, Ordering::Relaxed);
+            }
+            if let Some(info) = info {
+                info(&SearchInfo {
+                    depth,
+                    score,
+                    nodes: self.stats.total_nodes(),
+                    qnodes: self.stats.qnodes.load(Ordering::Relaxed),
+                    fail_lows,
+                    fail_highs,
+                    hashfull: self.tt.hashfull(),
+                    elapsed: self.start.elapsed(),
+                    pv: pv.clone(),
+                });
+            }
+
+            depth += 1; // Increase depth for the next iteration
+            if !game_on {
+                break;
             }
         }
 
-        if board.side_to_move() == Color::Black {
-            hash ^= self.side_to_move_key;
+        pv.first().copied()
+    }
+
+    // Counts a node and says whether the search has to unwind. `count` is the counter
+    // value before this node; the stop flag is looked at every node, the clock only
+    // every 1024 nodes.
+    fn should_abort(&self, count: u64) -> bool {
+        if self.can_abort.load(Ordering::Relaxed)
+            && (self.stop.load(Ordering::Relaxed)
+                || (count.is_multiple_of(1024) && self.limits.out_of_budget(self.start, self.stats.total_nodes()))) {
+            self.aborted.store(true, Ordering::Relaxed);
         }
-        hash
+        self.aborted.load(Ordering::Relaxed)
     }
 
-    pub fn update_hash(
-        &self,
-        hash: u64,
-        from_square: usize,
-        to_square: usize,
-        piece: Piece,
-        side_to_move: Color,
-    ) -> u64 {
-        let piece_index = piece as usize;
-        let mut new_hash = hash;
+    /// Orders the moves to search: the PV or TT move, captures by MVV-LVA (most valuable
+    /// victim, then least valuable attacker) as long as SEE says they don't lose material,
+    /// quiet promotions, the killers of this ply, the countermove, the other quiet moves
+    /// by history score and finally the losing captures.
+    fn categorize_moves(&self, position: &Position, previous_best: Option<Move>, ply: i32) -> Vec<Move> {
+        let board = position.board();
+        let mut best_move = previous_best;
+        if best_move.is_none()
+            && let Some(entry) = self.tt.get(position.key()) {
+            best_move = entry.best_move;
+        }
+        let countermove = self.history.countermove(board, position.last_move());
+
+        let enemies = board.colors(!board.side_to_move());
+        let mut scored = Vec::new();
+        board.generate_moves(|moves| {
+            for m in moves {
+                let en_passant = moves.piece == Piece::Pawn && m.from.file() != m.to.file() && !enemies.has(m.to);
+                let (kind, score) = if Some(m) == best_move {
+                    (MoveType::PreviousBest, 0)
+                } else if enemies.has(m.to) || en_passant {
+                    let mvv_lva = Self::capture_gain(board, m) * 8 - moves.piece as i32;
+                    // Taking something at least as valuable can't lose material, no need for SEE
+                    let safe = PIECE_VALUES[moves.piece as usize] <= Self::capture_gain(board, m);
+                    if safe || see(board, m) >= 0 {
+                        (MoveType::Capture, mvv_lva)
+                    } else {
+                        (MoveType::LosingCapture, mvv_lva)
+                    }
+                } else if let Some(promotion) = m.promotion {
+                    (MoveType::Promotion, PIECE_VALUES[promotion as usize])
+                } else if self.history.is_killer(ply, m) {
+                    (MoveType::Killer, 0)
+                } else if Some(m) == countermove {
+                    (MoveType::Countermove, 0)
+                } else {
+                    (MoveType::Normal, self.history.history(board.side_to_move(), m))
+                };
+                scored.push((kind, score, m));
+            }
+            false
+        });
 
-        new_hash ^= self.piece_keys[piece_index][from_square];
-        new_hash ^= self.piece_keys[piece_index][to_square];
-        new_hash ^= self.side_to_move_key;
-        new_hash
+        // Stable, so equal moves keep the generator's order
+        scored.sort_by_key(|&(kind, score, _)| (kind, -score));
+        scored.into_iter().map(|(_, _, m)| m).collect()
     }
-}
 
+    fn reduction_table(options: &EngineOptions) -> Vec<[i32; MAX_MOVES]> {
+        let base = options.lmr_base as f64 / 100.0;
+        let divisor = options.lmr_divisor as f64 / 100.0;
+        (0..MAX_PLY)
+            .map(|depth| {
+                let mut row = [0; MAX_MOVES];
+                for (moves, reduction) in row.iter_mut().enumerate().skip(1) {
+                    if depth > 0 {
+                        *reduction = (base + (depth as f64).ln() * (moves as f64).ln() / divisor).max(0.0) as i32;
+                    }
+                }
+                row
+            })
+            .collect()
+    }
 
-#[derive(Clone)]
-pub struct Entry {
-    pub score: i32,
-    pub depth: i32,
-    pub flag: flag_type,
-    pub best_move: String,
-}
-#[derive(Clone, Copy, PartialEq)]
-pub enum flag_type {
-    Exact,
-    Lower,
-    Upper,
-}
-pub struct transposition_table {
-    table: Mutex<HashMap<u64, Entry>>,
-}
+    // How much shallower the `index`th move (from 0) is searched. The search always
+    // goes at least one ply deep.
+    fn late_move_reduction(&self, depth: i32, index: usize) -> i32 {
+        let depth_index = (depth as usize).min(MAX_PLY - 1);
+        self.reductions[depth_index][(index + 1).min(MAX_MOVES - 1)].min(depth - 2).max(0)
+    }
 
-impl transposition_table {
-    pub fn new() -> Self {
-        transposition_table {
-            table: Mutex::new(HashMap::new()),
+    // Passing is only tried where it can matter and where zugzwang is unlikely: not in
+    // check, not right after another null move, not with only pawns left, and only when
+    // the static eval is already good enough to fail high
+    fn null_move_allowed(&self, position: &Position, depth: i32, beta: i32, static_eval: i32) -> bool {
+        let board = position.board();
+        let us = board.colors(board.side_to_move());
+        let pieces = us & !(board.pieces(Piece::Pawn) | board.pieces(Piece::King));
+
+        self.options.null_move
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && board.checkers().is_empty()
+            && !position.last_move_was_null()
+            && !pieces.is_empty()
+            && beta.abs() < MATE_BOUND
+            && static_eval >= beta
+    }
+
+    // Draws are worth `-contempt` to the side that started the search
+    fn draw_score(&self, ply: i32) -> i32 {
+        if ply % 2 == 0 {
+            -self.options.contempt
+        } else {
+            self.options.contempt
         }
     }
 
-    pub fn store(&self, hash: u64, entry: Entry) {
-        let mut table = self.table.lock().unwrap();
-        table.insert(hash, entry);
+    // The part of the previous PV that continues after `m`, if `m` follows it
+    fn pv_after(prev_pv: &[Move], m: Move) -> &[Move] {
+        match prev_pv.split_first() {
+            Some((&first, rest)) if first == m => rest,
+            _ => &[],
+        }
     }
 
-    pub fn get(&self, hash: u64) -> Option<Entry> {
-        let table = self.table.lock().unwrap();
-        table.get(&hash).map(|entry| {
-            Entry {
-               score: entry.score,
-                depth: entry.depth,
-                flag: entry.flag,
-                best_move: entry.best_move.clone(),
+    /// Searches the root moves to `depth` within `alpha..beta` and returns the best score
+    /// with its PV, or `None` if the search was aborted before the iteration finished.
+    /// A score at or outside the window is only a bound, see `iterative_deepening`.
+    fn search_root(&self, position: &Position, depth: i32, mut alpha: i32, beta: i32, prev_pv: &[Move]) -> Option<(i32, Vec<Move>)> {
+        let moves = self.categorize_moves(position, prev_pv.first().copied(), 0);
+        let mut position = position.clone();
+        let mut best: Option<(i32, Vec<Move>)> = None;
+        let mut line = Vec::new();
+
+        for (index, m) in moves.into_iter().enumerate() {
+            position.make_move(m);
+            let eval = self.pvs_child(&mut position, depth, 0, alpha, beta, index == 0, 0, 0, Self::pv_after(prev_pv, m), &mut line);
+            position.unmake_move();
+            if self.aborted.load(Ordering::Relaxed) {
+                return None;
             }
-        })
+
+            // Ties go to the move ordered first
+            if best.as_ref().is_none_or(|(score, _)| eval > *score) {
+                let mut pv = vec![m];
+                pv.extend_from_slice(&line);
+                best = Some((eval, pv));
+            }
+            alpha = alpha.max(eval);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best
     }
-}
 
-fn calculate_material(board: &Board) -> Result<(i32, i32), String> {
-    let piece_values = [1, 3, 3, 5, 9, 0];
-    let mut white_pts = 0;
-    let mut black_pts = 0;
-
-    let knight_pos_map: [i32; 64] = [
-        -1, 1, -1, -1, -1, -1, 1, -1,
-        -2, -1, 1, -1, -1, 1, -1, -2,
-        -2, 0, 2, 1, 1, 2, 0, -2,
-        -2, 1, 1, 3, 3, 1, 1, -2,
-        -2, 1, 1, 3, 3, 1, 1, -2,
-        -2, 0, 2, 1, 1, 2, 0, -2,
-        -2, -1, 1, -1, -1, 1, -1, -2,
-        -2, 1, -1, -1, -1, -1, 1, -2,
-    ];
-
-    let bishop_pos_map: [i32; 64] = [
-        -2, -1, 1, -1, -1, 1, -1, -2,
-        -1, 2, -1, 2, 2, -1, 2, -1,
-        1, 1, 3, 2, 2, 3, 1, 1,
-        -1, 2, 3, 4, 4, 3, 2, -1,
-        -1, 2, 3, 4, 4, 3, 2, -1,
-        1, 1, 3, 2, 2, 3, 1, 1,
-        -1, 2, -1, 2, 2, -1, 2, -1,
-        -2, -1, 1, -1, -1, 1, -1, -2,
-    ];
-
-    let queen_pos_map: [i32; 64] = [
-        -2, -2, -2, 1, 1, -2, -2, -2,
-        -2, 0, 1, 1, 1, 1, 0, -2,
-        -2, 1, 2, 2, 2, 2, 1, -2,
-        -2, 2, 2, 3, 3, 2, 2, -2,
-        -2, 2, 2, 3, 3, 2, 2, -2,
-        -2, 1, 2, 2, 2, 2, 1, -2,
-        -2, 0, 1, 1, 1, 1, 0, -2,
-        -2, -2, -2, 1, 1, -2, -2, -2,
-    ];
-
-    let king_begin_pos_map: [i32; 64] = [
-        -1, 2, 2, 1, 1, 2, 2, -1,
-        0, 0, 0, 0, 0, 0, 0, 0,
-        -2, -2, -2, -2, -2, -2, -2, -2,
-        -2, -2, -2, -2, -2, -2, -2, -2,
-        -2, -2, -2, -2, -2, -2, -2, -2,
-        -2, -2, -2, -2, -2, -2, -2, -2,
-        0, 0, 0, 0, 0, 0, 0, 0,
-        -1, 2, 2, 1, 1, 2, 2, -1,
-    ];
-
-    let king_end_pos_map: [i32; 64] = [
-        -2, -2, -2, 1, 1, -2, -2, -2,
-        -2, 0, 0, 1, 1, 0, 0, -2,
-        -2, 0, 1, 2, 2, 1, 0, -2,
-        -2, 1, 2, 3, 3, 2, 1, -2,
-        -2, 1, 2, 3, 3, 2, 1, -2,
-        -2, 0, 1, 2, 2, 1, 0, -2,
-        -2, 0, 0, 1, 1, 0, 0, -2,
-        -2, -2, -2, 1, 1, -2, -2, -2,
-    ];
-
-    let pawn_begin_pos_map: [i32; 64] = [
-        2, 2, 2, 2, 2, 2, 2, 2,
-        1, 1, 1, 1, 1, 1, 1, 1,
-        0, 0, 1, 2, 2, 1, 0, 0,
-        0, 0, 2, 3, 3, 2, 0, 0,
-        0, 0, 2, 3, 3, 2, 0, 0,
-        0, 0, 1, 2, 2, 1, 0, 0,
-        1, 1, 1, 1, 1, 1, 1, 1,
-        2, 2, 2, 2, 2, 2, 2, 2,
-    ];
-
-    for (piece, &value) in Piece::ALL.iter().zip(piece_values.iter()) {
-        let piece_bb = board.pieces(*piece);
-        let white_bb = piece_bb & board.colors(Color::White);
-        let black_bb = piece_bb & board.colors(Color::Black);
-
-        let white_count = white_bb.len() as i32;
-        let black_count = black_bb.len() as i32;
-
-        if *piece == Piece::Knight {
-            for i in 0..64 {
-                let square_bb = cozy_chess::BitBoard(1 << i);
-                if (white_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    white_pts += knight_pos_map[i] * value;
-                }
-                if (black_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    black_pts += knight_pos_map[i] * value;
-                }
+    /// Scores the move just made in `position` for the side that made it. The first move
+    /// gets the full window; the rest are first searched with a null window, just to
+    /// show they are no better than `alpha`, and only searched again with the full
+    /// window when that fails. A move with a `reduction` gets its null window search
+    /// that much shallower, and is searched again at full depth if it beats `alpha`.
+    /// `extensions` counts the plies of extension on the line, this move included.
+    #[allow(clippy::too_many_arguments)]
+    fn pvs_child(&self, position: &mut Position, depth: i32, ply: i32, alpha: i32, beta: i32, first: bool, reduction: i32, extensions: i32, prev_pv: &[Move], line: &mut Vec<Move>) -> i32 {
+        if !first {
+            let mut eval = -self.alpha_beta_search(position, depth - 1 - reduction, ply + 1, -alpha - 1, -alpha, prev_pv, line, true, None, extensions);
+            if reduction > 0 && eval > alpha && !self.aborted.load(Ordering::Relaxed) {
+                eval = -self.alpha_beta_search(position, depth - 1, ply + 1, -alpha - 1, -alpha, prev_pv, line, true, None, extensions);
             }
-        } else if *piece == Piece::Bishop {
-            for i in 0..64 {
-                let square_bb = cozy_chess::BitBoard(1 << i);
-                if (white_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    white_pts += bishop_pos_map[i] * value;
-                }
-                if (black_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    black_pts += bishop_pos_map[i] * value;
-                }
+            if eval <= alpha || eval >= beta || self.aborted.load(Ordering::Relaxed) {
+                return eval;
             }
-        } else if *piece == Piece::Queen {
-            for i in 0..64 {
-                let square_bb = cozy_chess::BitBoard(1 << i);
-                if (white_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    white_pts += queen_pos_map[i] * value;
-                }
-                if (black_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    black_pts += queen_pos_map[i] * value;
-                }
+        }
+        -self.alpha_beta_search(position, depth - 1, ply + 1, -beta, -alpha, prev_pv, line, true, None, extensions)
+    }
+
+    // A pawn pushed to the sixth or seventh rank with no enemy pawn left to stop it
+    fn is_passed_pawn_push(board: &Board, m: Move) -> bool {
+        let color = board.side_to_move();
+        if board.piece_on(m.from) != Some(Piece::Pawn) || m.to.rank().relative_to(color) < Rank::Sixth {
+            return false;
+        }
+        (board.colored_pieces(!color, Piece::Pawn) & passed_pawn_mask(color, m.to)).is_empty()
+    }
+
+    /// Negamax alpha-beta: scores are always from the point of view of the side to move
+    /// in `board`, so a child's score is negated on the way back up. `ply` is the distance
+    /// from the root. The moves that lead to the returned score are left in `pv`.
+    ///
+    /// `excluded` is left out of the moves, for the singular extension test, and such a
+    /// search neither uses nor fills the transposition table. `extensions` is the number
+    /// of plies the line to here was extended by.
+    #[allow(clippy::too_many_arguments)]
+    fn alpha_beta_search(&self, position: &mut Position, depth: i32, ply: i32, mut alpha: i32, mut beta: i32, prev_pv: &[Move], pv: &mut Vec<Move>, allow_null: bool, excluded: Option<Move>, extensions: i32) -> i32 {
+        pv.clear();
+        if depth <= 0 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+
+        if self.should_abort(self.stats.nodes.fetch_add(1, Ordering::Relaxed)) {
+            return 0;
+        }
+        if position.is_repetition() {
+            return self.draw_score(ply);
+        }
+
+        // Mate distance pruning: nothing here beats mating on the next move or loses
+        // faster than being mated right now, so a window outside that is already decided
+        alpha = alpha.max(-MATE + ply);
+        beta = beta.min(MATE - ply - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+        let hash = position.key();
+        let pv_node = beta - alpha > 1;
+
+        // Cutoffs are left out of PV nodes so the PV stays whole
+        let tt_entry = if excluded.is_none() { self.tt.get(hash) } else { None };
+        if let Some(entry) = tt_entry
+            && entry.depth >= depth
+            && !pv_node {
+            let score = score_from_tt(entry.score, ply);
+            match entry.flag {
+                FlagType::Exact => return score,
+                FlagType::Lower if score >= beta => return score,
+                FlagType::Upper if score <= alpha => return score,
+                _ => {}
             }
-        } else if *piece == Piece::King && unsafe { GAME_STAGE != 3 } {
-            for i in 0..64 {
-                let square_bb = cozy_chess::BitBoard(1 << i);
-                if (white_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    white_pts += king_begin_pos_map[i] * value;
-                }
-                if (black_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    black_pts += king_begin_pos_map[i] * value;
-                }
+        }
+
+        let in_check = !position.board().checkers().is_empty();
+        let static_eval = if in_check { -INFINITY } else { self.evaluate(position) };
+        if self.options.shallow_pruning && !pv_node && !in_check && excluded.is_none() && beta.abs() < MATE_BOUND {
+            // Reverse futility pruning: this far above beta, even a bad move is unlikely
+            // to lose it all before the horizon
+            let margin = REVERSE_FUTILITY_MARGIN * depth;
+            if depth <= REVERSE_FUTILITY_DEPTH && static_eval - margin >= beta {
+                return static_eval - margin;
             }
-        } else if *piece == Piece::King && unsafe { GAME_STAGE == 3 } {
-            for i in 0..64 {
-                let square_bb = cozy_chess::BitBoard(1 << i);
-                if (white_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    white_pts += king_end_pos_map[i] * value;
+
+            // Razoring: this far below alpha only winning material can help, so check
+            // that with quiescence and give up if it doesn't
+            if depth <= RAZOR_DEPTH && static_eval + RAZOR_MARGIN * depth < alpha {
+                let score = self.quiescence(position, ply, alpha, alpha + 1);
+                if self.aborted.load(Ordering::Relaxed) {
+                    return 0;
                 }
-                if (black_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    black_pts += king_end_pos_map[i] * value;
+                if score <= alpha {
+                    return score;
                 }
             }
-        } else if *piece == Piece::Pawn && unsafe { GAME_STAGE != 3 } {
-            for i in 0..64 {
-                let square_bb = cozy_chess::BitBoard(1 << i);
-                if (white_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    white_pts += pawn_begin_pos_map[i] * value;
+        }
+
+        let mut line = Vec::new();
+        // `make_null_move` refuses in check, and then there is nothing to unmake
+        if allow_null && !pv_node && excluded.is_none() && self.null_move_allowed(position, depth, beta, static_eval)
+            && position.make_null_move() {
+            // Null-move pruning: if passing the turn still fails high, some real move
+            // would too. The reduction grows with depth.
+            let reduction = NULL_MOVE_REDUCTION + depth / 4;
+            let score = -self.alpha_beta_search(position, depth - 1 - reduction, ply + 1, -beta, -beta + 1, &[], &mut line, true, None, extensions);
+            position.unmake_move();
+            if self.aborted.load(Ordering::Relaxed) {
+                return 0;
+            }
+
+            if score >= beta {
+                // A mate found after passing isn't a real one
+                let score = if score >= MATE_BOUND { beta } else { score };
+                if depth < NULL_MOVE_VERIFY_DEPTH {
+                    return score;
+                }
+                // Deep down a wrong cutoff costs a lot, so check with a reduced search
+                // without the null move that this isn't zugzwang
+                let verified = self.alpha_beta_search(position, depth - reduction, ply, beta - 1, beta, prev_pv, &mut line, false, None, extensions);
+                if self.aborted.load(Ordering::Relaxed) {
+                    return 0;
                 }
-                if (black_bb & square_bb) != cozy_chess::BitBoard(0) {
-                    black_pts += pawn_begin_pos_map[i] * value;
+                if verified >= beta {
+                    return score;
                 }
             }
-        } else {
-            white_pts += white_count * value;
-            black_pts += black_count * value;
         }
-    }
-    //println!("white eval: {}", white_pts);
-    //println!("black eval: {}", black_pts);
-    Ok((white_pts, black_pts))
-}
+
+        let moves = self.categorize_moves(position, prev_pv.first().copied(), ply);
+        if moves.is_empty() {
+            return if position.board().checkers().is_empty() {
+                self.draw_score(ply)
+            } else {
+                -MATE + ply
+            };
+        }
+        // Checkmate on the hundredth half-move still counts, so this comes after the mate test
+        if position.board().halfmove_clock() >= 100 {
+            return self.draw_score(ply);
+        }
+
+        // At most one ply of extension for every two plies of line, so no line grows much
+        // past twice the depth it was started with
+        let can_extend = 2 * extensions <= ply;
+        let single_reply = moves.len() == 1;
+        let singular_move = if can_extend { self.singular_move(position, depth, ply, tt_entry, extensions) } else { None };
+
+        let original_alpha = alpha;
+        let mut max_eval = -INFINITY;
+        let mut best_move = None;
+        let mut quiets_tried = Vec::new();
+        // Futility pruning: near the horizon, quiet moves can't make up for a static eval
+        // this far below alpha. What they could have reached still bounds the result.
+        let futility_value = static_eval + FUTILITY_MARGIN * (depth + 1);
+        let futile = self.options.shallow_pruning
+            && !pv_node
+            && !in_check
+            && depth <= FUTILITY_DEPTH
+            && alpha.abs() < MATE_BOUND
+            && futility_value <= alpha;
+        for (index, m) in moves.into_iter().enumerate() {
+            if Some(m) == excluded {
+                continue;
+            }
+            let quiet = Self::is_quiet(position.board(), m);
+            let passed_pawn_push = Self::is_passed_pawn_push(position.board(), m);
+            position.make_move(m);
+            let gives_check = !position.board().checkers().is_empty();
+
+            if futile && quiet && !gives_check && max_eval > -MATE_BOUND {
+                max_eval = max_eval.max(futility_value);
+                position.unmake_move();
+                continue;
+            }
+
+            // Late move pruning: with good ordering, quiet moves this late almost never
+            // matter at shallow depth. Checks are kept, they might mate.
+            if quiet
+                && !pv_node
+                && !in_check
+                && !gives_check
+                && depth <= self.options.lmp_max_depth
+                && quiets_tried.len() as i32 >= self.options.lmp_base + depth * depth
+                && max_eval > -MATE_BOUND {
+                position.unmake_move();
+                continue;
+            }
+
+            // Late move reductions, for quiet moves that don't give or get out of check
+            let reduction = if quiet
+                && !in_check
+                && !gives_check
+                && depth >= self.options.lmr_min_depth
+                && index as i32 >= self.options.lmr_min_moves {
+                // Less in PV nodes, where the moves matter more
+                (self.late_move_reduction(depth, index) - pv_node as i32).max(0)
+            } else {
+                0
+            };
+            // Check, single reply, passed pawn push and singular extensions. One ply at most,
+            // whichever applies.
+            let extension = (can_extend
+                && (gives_check || single_reply || passed_pawn_push || Some(m) == singular_move)) as i32;
+            let eval = self.pvs_child(position, depth + extension, ply, alpha, beta, index == 0, reduction, extensions + extension, Self::pv_after(prev_pv, m), &mut line);
+            position.unmake_move();
+            if self.aborted.load(Ordering::Relaxed) {
+                return 0;
+            }
+
+            if eval > max_eval {
+                max_eval = eval;
+            }
+            if eval > alpha {
+                alpha = eval;
+                best_move = Some(m);
+                pv.clear();
+                pv.push(m);
+                pv.extend_from_slice(&line);
+            }
+            if beta <= alpha {
+                if quiet {
+                    self.history.update(position.board(), ply, depth, m, position.last_move(), &quiets_tried);
+                }
+                break;
+            }
+            if quiet {
+                quiets_tried.push(m);
+            }
+        }
+
+        if excluded.is_some() {
+            return max_eval;
+        }
+
+        // Failing low says nothing about which move was best, so no move is stored then
+        let flag = if max_eval >= beta {
+            FlagType::Lower
+        } else if max_eval > original_alpha {
+            FlagType::Exact
+        } else {
+            FlagType::Upper
+        };
+        self.tt.store(hash, Entry {
+            score: score_to_tt(max_eval, ply),
+            depth,
+            flag,
+            best_move,
+        });
+
+        max_eval
+    }
+
+    // The TT move, if searching every other move to half the depth can't get within a
+    // margin of its score. A move that much better than the rest is worth a ply more.
+    fn singular_move(&self, position: &mut Position, depth: i32, ply: i32, tt_entry: Option<Entry>, extensions: i32) -> Option<Move> {
+        let entry = tt_entry?;
+        let tt_move = entry.best_move?;
+        if depth < SINGULAR_DEPTH
+            || entry.depth < depth - SINGULAR_TT_DEPTH
+            || entry.flag == FlagType::Upper {
+            return None;
+        }
+        let tt_score = score_from_tt(entry.score, ply);
+        if tt_score.abs() >= MATE_BOUND {
+            return None;
+        }
+
+        let singular_beta = tt_score - SINGULAR_MARGIN * depth;
+        let mut line = Vec::new();
+        let score = self.alpha_beta_search(position, (depth - 1) / 2, ply, singular_beta - 1, singular_beta, &[], &mut line, false, Some(tt_move), extensions);
+        (score < singular_beta && !self.aborted.load(Ordering::Relaxed)).then_some(tt_move)
+    }
+
+    // `evaluate`, with the pawn structure looked up in the pawn table first
+    fn evaluate(&self, position: &Position) -> i32 {
+        let key = position.pawn_key();
+        let pawns = self.pawns.get(key).unwrap_or_else(|| {
+            let pawns = pawn_structure(position.board());
+            self.pawns.store(key, &pawns);
+            pawns
+        });
+        evaluate_with_pawns(position.board(), &pawns)
+    }
+
+    // Neither a capture nor a promotion
+    fn is_quiet(board: &Board, m: Move) -> bool {
+        let en_passant = board.piece_on(m.from) == Some(Piece::Pawn) && m.from.file() != m.to.file();
+        !board.colors(!board.side_to_move()).has(m.to) && !en_passant && m.promotion.is_none()
+    }
+
+    // Captures, promotions and en passant, most valuable victim first
+    fn noisy_moves(board: &Board) -> Vec<Move> {
+        let enemies = board.colors(!board.side_to_move());
+        let mut noisy = Vec::new();
+        board.generate_moves(|moves| {
+            for m in moves {
+                let en_passant = moves.piece == Piece::Pawn && m.from.file() != m.to.file();
+                if enemies.has(m.to) || en_passant || m.promotion.is_some() {
+                    noisy.push(m);
+                }
+            }
+            false
+        });
+        noisy.sort_by_key(|m| -Self::capture_gain(board, *m));
+        noisy
+    }
+
+    // Material won by a capture or promotion, before any recapture
+    fn capture_gain(board: &Board, m: Move) -> i32 {
+        let victim = match board.piece_on(m.to) {
+            Some(piece) if board.color_on(m.to) != Some(board.side_to_move()) => PIECE_VALUES[piece as usize],
+            Some(_) => 0,
+            // En passant
+            None if board.piece_on(m.from) == Some(Piece::Pawn) && m.from.file() != m.to.file() => PIECE_VALUES[Piece::Pawn as usize],
+            None => 0,
+        };
+        let promotion = m.promotion.map_or(0, |p| PIECE_VALUES[p as usize] - PIECE_VALUES[Piece::Pawn as usize]);
+        victim + promotion
+    }
+
+    /// Keeps searching captures and promotions (and every move while in check) past the
+    /// nominal depth, so the eval is only taken in quiet positions. The side to move may
+    /// "stand pat" on the static eval instead of capturing, except when in check.
+    fn quiescence(&self, position: &mut Position, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_abort(self.stats.qnodes.fetch_add(1, Ordering::Relaxed)) {
+            return 0;
+        }
+        // Checks answered by checks could otherwise go on for as long as the position lets them
+        if ply >= MAX_PLY as i32 {
+            return self.evaluate(position);
+        }
+        // Kept aside, `position` itself is borrowed mutably for the children
+        let board = position.board().clone();
+        let in_check = !board.checkers().is_empty();
+
+        let stand_pat = self.evaluate(position);
+        let moves = if in_check {
+            let mut evasions = Vec::new();
+            board.generate_moves(|moves| {
+                evasions.extend(moves);
+                false
+            });
+            evasions
+        } else {
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            Self::noisy_moves(&board)
+        };
+
+        let mut best = if in_check { -MATE + ply } else { stand_pat };
+
+        for m in moves {
+            // Delta pruning: even winning this piece for free can't bring the score back.
+            // The most it could have been still bounds the result, so a fail-low stays a
+            // true upper bound for the transposition table.
+            let optimistic = stand_pat + Self::capture_gain(&board, m) + DELTA_MARGIN;
+            if !in_check && optimistic <= alpha {
+                best = best.max(optimistic);
+                continue;
+            }
+
+            position.make_move(m);
+            let eval = -self.quiescence(position, ply + 1, -beta, -alpha);
+            position.unmake_move();
+            if self.aborted.load(Ordering::Relaxed) {
+                return 0;
+            }
+
+            best = best.max(eval);
+            alpha = alpha.max(eval);
+            if beta <= alpha {
+                break;
+            }
+        }
+
+        best
+    }
+}
+
+/// Milliseconds to spend on a move in `board` for `mycolor`, which has `time` left.
+pub fn determine_time(board: &Board, time: i32, game_on: bool, mycolor: Color) -> i32 {
+    if !game_on {
+        return 0;
+    }
+
+    if time < 10_000 {
+        return 200;
+    }
+
+    // Worth thinking longer when behind
+    let eval = if board.side_to_move() == mycolor { evaluate(board) } else { -evaluate(board) };
+    if eval < 0 {
+        return 750;
+    }
+
+    500
+}
+
+pub struct Zobrist {
+    // Indexed by `color * 6 + piece`, then square
+    pub piece_keys: [[u64; BOARD_SQUARES]; PIECE_TYPES],
+    // Indexed by color, then short (0) or long (1) castling
+    pub castle_keys: [[u64; 2]; 2],
+    // Only hashed when an en passant capture is actually on the board
+    pub en_passant_keys: [u64; 8],
+    pub side_to_move_key: u64,
+    // Indexed by `color * 6 + piece`, then how many of that piece came before this one
+    pub material_keys: [[u64; MAX_PIECE_COUNT]; PIECE_TYPES],
+}
+
+impl Default for Zobrist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// splitmix64, keys are the same every run so hashes can be logged and compared
+fn next_key(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Zobrist {
+    pub fn new() -> Self {
+        Self::with_seed(ZOBRIST_SEED)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        let mut state = seed;
+        let mut piece_keys = [[0u64; BOARD_SQUARES]; PIECE_TYPES];
+        for piece_row in piece_keys.iter_mut() {
+            for key in piece_row.iter_mut() {
+                *key = next_key(&mut state);
+            }
+        }
+        let mut castle_keys = [[0u64; 2]; 2];
+        for key in castle_keys.iter_mut().flatten() {
+            *key = next_key(&mut state);
+        }
+        let mut en_passant_keys = [0u64; 8];
+        for key in en_passant_keys.iter_mut() {
+            *key = next_key(&mut state);
+        }
+
+        let side_to_move_key = next_key(&mut state);
+        let mut material_keys = [[0u64; MAX_PIECE_COUNT]; PIECE_TYPES];
+        for key in material_keys.iter_mut().flatten() {
+            *key = next_key(&mut state);
+        }
+
+        Zobrist {
+            piece_keys,
+            castle_keys,
+            en_passant_keys,
+            side_to_move_key,
+            material_keys,
+        }
+    }
+
+    pub fn piece_key(&self, color: Color, piece: Piece, square: Square) -> u64 {
+        self.piece_keys[color as usize * 6 + piece as usize][square as usize]
+    }
+
+    pub fn castle_key(&self, board: &Board, color: Color) -> u64 {
+        let rights = board.castle_rights(color);
+        let mut key = 0;
+        if rights.short.is_some() {
+            key ^= self.castle_keys[color as usize][0];
+        }
+        if rights.long.is_some() {
+            key ^= self.castle_keys[color as usize][1];
+        }
+        key
+    }
+
+    pub fn hash_position(&self, board: &Board) -> u64 {
+        let mut hash: u64 = 0;
+
+        for color in [Color::White, Color::Black] {
+            for piece in Piece::ALL {
+                for square in board.colored_pieces(color, piece) {
+                    hash ^= self.piece_key(color, piece, square);
+                }
+            }
+            hash ^= self.castle_key(board, color);
+        }
+        if let Some(file) = board.en_passant() {
+            hash ^= self.en_passant_keys[file as usize];
+        }
+        if board.side_to_move() == Color::Black {
+            hash ^= self.side_to_move_key;
+        }
+        hash
+    }
+
+    /// Only the pawns, so positions with the same pawn structure share a key.
+    pub fn hash_pawns(&self, board: &Board) -> u64 {
+        let mut hash = 0;
+        for color in [Color::White, Color::Black] {
+            for square in board.colored_pieces(color, Piece::Pawn) {
+                hash ^= self.piece_key(color, Piece::Pawn, square);
+            }
+        }
+        hash
+    }
+
+    /// Only how many of each piece each side has, wherever they stand.
+    pub fn hash_material(&self, board: &Board) -> u64 {
+        let mut hash = 0;
+        for color in [Color::White, Color::Black] {
+            for piece in Piece::ALL {
+                let count = board.colored_pieces(color, piece).len() as usize;
+                for index in 0..count {
+                    hash ^= self.material_key(color, piece, index);
+                }
+            }
+        }
+        hash
+    }
+
+    // Key for the `index`th piece of a kind, counting from zero
+    fn material_key(&self, color: Color, piece: Piece, index: usize) -> u64 {
+        self.material_keys[color as usize * 6 + piece as usize][index.min(MAX_PIECE_COUNT - 1)]
+    }
+}
+
+
+/// A board together with its position, pawn and material keys, all kept up to date
+/// move by move instead of being recomputed. Moves are made and unmade on a stack, so
+/// one `Position` is enough for a whole line of the search.
+#[derive(Clone)]
+pub struct Position {
+    board: Board,
+    key: u64,
+    pawn_key: u64,
+    material_key: u64,
+    undo: Vec<Undo>,
+    // Every move played to get here, the game before the search included. `None` is a
+    // null move.
+    moves: Vec<Option<Move>>,
+    // Keys of every earlier position, the game before the search included
+    history: Vec<u64>,
+    // Where in `history` the search started
+    root: usize,
+}
+
+#[derive(Clone)]
+struct Undo {
+    board: Board,
+    key: u64,
+    pawn_key: u64,
+    material_key: u64,
+}
+
+impl Position {
+    pub fn new(board: Board) -> Self {
+        Position {
+            key: ZOBRIST.hash_position(&board),
+            pawn_key: ZOBRIST.hash_pawns(&board),
+            material_key: ZOBRIST.hash_material(&board),
+            board,
+            undo: Vec::new(),
+            moves: Vec::new(),
+            history: Vec::new(),
+            root: 0,
+        }
+    }
+
+    /// The position after `moves` from `start`, remembering every position on the way
+    /// for repetition detection. Searches start from here.
+    pub fn with_history(start: Board, moves: &[Move]) -> Self {
+        let mut position = Position::new(start);
+        for &m in moves {
+            position.make_move(m);
+        }
+        // The game can't be taken back, only the keys are needed
+        position.undo.clear();
+        position.root = position.history.len();
+        position
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    pub fn pawn_key(&self) -> u64 {
+        self.pawn_key
+    }
+
+    pub fn material_key(&self) -> u64 {
+        self.material_key
+    }
+
+    /// The move that led to this position, if it is known and wasn't a null move.
+    pub fn last_move(&self) -> Option<Move> {
+        self.moves.last().copied().flatten()
+    }
+
+    pub fn last_move_was_null(&self) -> bool {
+        self.moves.last() == Some(&None)
+    }
+
+    /// Plays a legal move, updating the keys from what it changes on the board.
+    pub fn make_move(&mut self, m: Move) {
+        self.undo.push(Undo {
+            board: self.board.clone(),
+            key: self.key,
+            pawn_key: self.pawn_key,
+            material_key: self.material_key,
+        });
+        self.history.push(self.key);
+        self.moves.push(Some(m));
+        self.board.play_unchecked(m);
+        let before = &self.undo[self.undo.len() - 1].board;
+
+        let us = before.side_to_move();
+        let them = !us;
+        let piece = before.piece_on(m.from).expect("Rust: no piece to move");
+
+        let mut key = self.key ^ ZOBRIST.side_to_move_key;
+        // Castling rights and en passant are read off the boards rather than worked out again
+        for color in [Color::White, Color::Black] {
+            key ^= ZOBRIST.castle_key(before, color) ^ ZOBRIST.castle_key(&self.board, color);
+        }
+        if let Some(file) = before.en_passant() {
+            key ^= ZOBRIST.en_passant_keys[file as usize];
+        }
+        if let Some(file) = self.board.en_passant() {
+            key ^= ZOBRIST.en_passant_keys[file as usize];
+        }
+
+        if piece == Piece::King && before.colors(us).has(m.to) {
+            // Castling is encoded as the king taking its own rook
+            let rank = m.from.rank();
+            let (king_to, rook_to) = if m.to.file() > m.from.file() {
+                (Square::new(cozy_chess::File::G, rank), Square::new(cozy_chess::File::F, rank))
+            } else {
+                (Square::new(cozy_chess::File::C, rank), Square::new(cozy_chess::File::D, rank))
+            };
+            key ^= ZOBRIST.piece_key(us, Piece::King, m.from) ^ ZOBRIST.piece_key(us, Piece::King, king_to);
+            key ^= ZOBRIST.piece_key(us, Piece::Rook, m.to) ^ ZOBRIST.piece_key(us, Piece::Rook, rook_to);
+            self.key = key;
+            self.check_keys();
+            return;
+        }
+
+        let mut pawn_key = self.pawn_key;
+        let mut material_key = self.material_key;
+
+        // A pawn changing files onto an empty square is taking en passant
+        let captured = match before.piece_on(m.to) {
+            Some(victim) => Some((victim, m.to)),
+            None if piece == Piece::Pawn && m.from.file() != m.to.file() => {
+                Some((Piece::Pawn, Square::new(m.to.file(), m.from.rank())))
+            }
+            None => None,
+        };
+        if let Some((victim, square)) = captured {
+            key ^= ZOBRIST.piece_key(them, victim, square);
+            if victim == Piece::Pawn {
+                pawn_key ^= ZOBRIST.piece_key(them, victim, square);
+            }
+            let count = before.colored_pieces(them, victim).len() as usize;
+            material_key ^= ZOBRIST.material_key(them, victim, count - 1);
+        }
+
+        key ^= ZOBRIST.piece_key(us, piece, m.from);
+        if piece == Piece::Pawn {
+            pawn_key ^= ZOBRIST.piece_key(us, piece, m.from);
+        }
+        match m.promotion {
+            Some(promoted) => {
+                key ^= ZOBRIST.piece_key(us, promoted, m.to);
+                let pawns = before.colored_pieces(us, Piece::Pawn).len() as usize;
+                let promoted_count = before.colored_pieces(us, promoted).len() as usize;
+                material_key ^= ZOBRIST.material_key(us, Piece::Pawn, pawns - 1);
+                material_key ^= ZOBRIST.material_key(us, promoted, promoted_count);
+            }
+            None => {
+                key ^= ZOBRIST.piece_key(us, piece, m.to);
+                if piece == Piece::Pawn {
+                    pawn_key ^= ZOBRIST.piece_key(us, piece, m.to);
+                }
+            }
+        }
+
+        self.key = key;
+        self.pawn_key = pawn_key;
+        self.material_key = material_key;
+        self.check_keys();
+    }
+
+    /// Passes the turn to the other side, for null-move pruning. Not allowed in check,
+    /// then nothing happens and `false` is returned.
+    pub fn make_null_move(&mut self) -> bool {
+        let Some(board) = self.board.null_move() else {
+            return false;
+        };
+        self.undo.push(Undo {
+            board: std::mem::replace(&mut self.board, board),
+            key: self.key,
+            pawn_key: self.pawn_key,
+            material_key: self.material_key,
+        });
+        self.history.push(self.key);
+        self.moves.push(None);
+
+        let before = &self.undo[self.undo.len() - 1].board;
+        self.key ^= ZOBRIST.side_to_move_key;
+        if let Some(file) = before.en_passant() {
+            self.key ^= ZOBRIST.en_passant_keys[file as usize];
+        }
+        self.check_keys();
+        true
+    }
+
+    /// Takes back the last `make_move` or `make_null_move`.
+    pub fn unmake_move(&mut self) {
+        let undo = self.undo.pop().expect("Rust: no move to unmake");
+        self.history.pop();
+        self.moves.pop();
+        self.board = undo.board;
+        self.key = undo.key;
+        self.pawn_key = undo.pawn_key;
+        self.material_key = undo.material_key;
+    }
+
+    /// Whether this position already came up since the search started, or twice in the
+    /// game before it. Either way best play can force a draw by repetition. Only positions
+    /// since the last capture or pawn move are looked at, nothing earlier can repeat.
+    pub fn is_repetition(&self) -> bool {
+        let mut reversible = (self.board.halfmove_clock() as usize).min(self.history.len());
+        // Nothing before a null move counts, the game never went through it
+        if let Some(since_null) = self.moves.iter().rev().take(reversible).position(|m| m.is_none()) {
+            reversible = since_null;
+        }
+        let mut before_root = 0;
+        // Only positions with the same side to move can match
+        for back in (2..=reversible).step_by(2) {
+            let index = self.history.len() - back;
+            if self.history[index] != self.key {
+                continue;
+            }
+            if index >= self.root {
+                return true;
+            }
+            before_root += 1;
+            if before_root == 2 {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Debug builds recompute every key from scratch to catch a bad incremental update
+    fn check_keys(&self) {
+        debug_assert_eq!(self.key, ZOBRIST.hash_position(&self.board), "position key out of sync: {}", self.board);
+        debug_assert_eq!(self.pawn_key, ZOBRIST.hash_pawns(&self.board), "pawn key out of sync: {}", self.board);
+        debug_assert_eq!(self.material_key, ZOBRIST.hash_material(&self.board), "material key out of sync: {}", self.board);
+    }
+}
+
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub score: i32,
+    pub depth: i32,
+    pub flag: FlagType,
+    pub best_move: Option<Move>,
+}
+#[derive(Clone, Copy, PartialEq)]
+pub enum FlagType {
+    Exact,
+    Lower,
+    Upper,
+}
+
+const ENTRIES_PER_BUCKET: usize = 4;
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 4096;
+// Ages wrap around in the 6 bits they get
+const AGE_MASK: u64 = 0x3f;
+// How much shallower than the entry already there a result for the same position may be
+// and still replace it
+const REPLACE_DEPTH_MARGIN: i32 = 3;
+
+/// Fixed-size transposition table shared by all search threads without locking.
+///
+/// Every entry is packed into one `AtomicU64`, so a reader always sees a whole entry as
+/// some thread wrote it:
+///
+/// | bits  | field                                          |
+/// |-------|------------------------------------------------|
+/// | 0-15  | best move (from, to, promotion), 0 for none    |
+/// | 16-31 | score as `i16`                                 |
+/// | 32-39 | depth                                          |
+/// | 40-41 | bound, 0 for an empty slot                     |
+/// | 42-47 | age of the search that wrote it                |
+/// | 48-63 | low 16 bits of the position key                |
+///
+/// Entries live in buckets of `ENTRIES_PER_BUCKET`. A new position replaces the slot
+/// holding the least useful entry: shallow ones, and ones left over from older searches.
+/// A position already in the table only gets overwritten by a result about as deep, an
+/// exact one, or any result once the old one is from an earlier search.
+pub struct TranspositionTable {
+    entries: Vec<AtomicU64>,
+    buckets: usize,
+    age: AtomicU64,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl TranspositionTable {
+    /// A table of `mb` megabytes. With 0 nothing is ever stored, which turns the table off.
+    pub fn new(mb: usize) -> Self {
+        let bucket_bytes = ENTRIES_PER_BUCKET * std::mem::size_of::<AtomicU64>();
+        let buckets = mb * 1024 * 1024 / bucket_bytes;
+        TranspositionTable {
+            entries: (0..buckets * ENTRIES_PER_BUCKET).map(|_| AtomicU64::new(0)).collect(),
+            buckets,
+            age: AtomicU64::new(0),
+        }
+    }
+
+    pub fn size_mb(&self) -> usize {
+        self.entries.len() * std::mem::size_of::<AtomicU64>() / (1024 * 1024)
+    }
+
+    fn bucket(&self, hash: u64) -> &[AtomicU64] {
+        if self.buckets == 0 {
+            return &[];
+        }
+        // Maps the hash onto the buckets without needing a power of two size
+        let index = ((hash as u128 * self.buckets as u128) >> 64) as usize;
+        &self.entries[index * ENTRIES_PER_BUCKET..(index + 1) * ENTRIES_PER_BUCKET]
+    }
+
+    /// Starts a new search. Entries from earlier ones become the first to be replaced.
+    pub fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn current_age(&self) -> u64 {
+        self.age.load(Ordering::Relaxed) & AGE_MASK
+    }
+
+    pub fn store(&self, hash: u64, entry: Entry) {
+        let key = hash & 0xffff;
+        let age = self.current_age();
+        let bucket = self.bucket(hash);
+        let Some(mut victim) = bucket.first() else {
+            return;
+        };
+        let mut victim_worth = i32::MAX;
+        for slot in bucket {
+            let old = slot.load(Ordering::Relaxed);
+            let old_age = (old >> 42) & AGE_MASK;
+            let empty = (old >> 40) & 0b11 == 0;
+            if old >> 48 == key || empty {
+                let mut entry = entry;
+                if !empty {
+                    let old_depth = ((old >> 32) & 0xff) as i32;
+                    if entry.depth < old_depth - REPLACE_DEPTH_MARGIN && entry.flag != FlagType::Exact && old_age == age {
+                        return;
+                    }
+                    // Keep the old best move if the new entry doesn't have one
+                    if entry.best_move.is_none() {
+                        entry.best_move = unpack_move(old as u16);
+                    }
+                }
+                slot.store(pack_entry(key, age, &entry), Ordering::Relaxed);
+                return;
+            }
+
+            // Depth preferred, but every search of age counts as much as 8 plies
+            let age_distance = ((age + AGE_MASK + 1 - old_age) & AGE_MASK) as i32;
+            let worth = ((old >> 32) & 0xff) as i32 - 8 * age_distance;
+            if worth < victim_worth {
+                victim = slot;
+                victim_worth = worth;
+            }
+        }
+        victim.store(pack_entry(key, age, &entry), Ordering::Relaxed);
+    }
+
+    pub fn get(&self, hash: u64) -> Option<Entry> {
+        let key = hash & 0xffff;
+        self.bucket(hash).iter()
+            .map(|slot| slot.load(Ordering::Relaxed))
+            .find(|&data| data >> 48 == key && (data >> 40) & 0b11 != 0)
+            .map(unpack_entry)
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.entries {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Permille of the table used by the current search, sampled from the first entries.
+    pub fn hashfull(&self) -> u32 {
+        let age = self.current_age();
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        if sample.is_empty() {
+            return 0;
+        }
+        let used = sample.iter()
+            .map(|slot| slot.load(Ordering::Relaxed))
+            .filter(|&data| (data >> 40) & 0b11 != 0 && (data >> 42) & AGE_MASK == age)
+            .count();
+        (used * 1000 / sample.len()) as u32
+    }
+}
+
+fn pack_entry(key: u64, age: u64, entry: &Entry) -> u64 {
+    let bound: u64 = match entry.flag {
+        FlagType::Exact => 1,
+        FlagType::Lower => 2,
+        FlagType::Upper => 3,
+    };
+    pack_move(entry.best_move) as u64
+        | (pack_score(entry.score) as u16 as u64) << 16
+        | (entry.depth.clamp(0, 255) as u64) << 32
+        | bound << 40
+        | age << 42
+        | key << 48
+}
+
+fn unpack_entry(data: u64) -> Entry {
+    let flag = match (data >> 40) & 0b11 {
+        1 => FlagType::Exact,
+        2 => FlagType::Lower,
+        _ => FlagType::Upper,
+    };
+    Entry {
+        score: unpack_score((data >> 16) as u16 as i16),
+        depth: ((data >> 32) & 0xff) as i32,
+        flag,
+        best_move: unpack_move(data as u16),
+    }
+}
+
+// 6 bits from, 6 bits to, 3 bits promotion (piece index + 1). A1A1 can't be a real move,
+// so 0 means no move.
+fn pack_move(m: Option<Move>) -> u16 {
+    match m {
+        Some(m) => m.from as u16 | (m.to as u16) << 6 | m.promotion.map_or(0, |p| p as u16 + 1) << 12,
+        None => 0,
+    }
+}
+
+fn unpack_move(data: u16) -> Option<Move> {
+    if data == 0 {
+        return None;
+    }
+    let promotion = (data >> 12) & 0b111;
+    Some(Move {
+        from: Square::index((data & 0x3f) as usize),
+        to: Square::index(((data >> 6) & 0x3f) as usize),
+        promotion: if promotion == 0 { None } else { Some(Piece::index(promotion as usize - 1)) },
+    })
+}
+
+// Every search score is within +-INFINITY, which fits
+fn pack_score(score: i32) -> i16 {
+    score.clamp(-INFINITY, INFINITY) as i16
+}
+
+fn unpack_score(score: i16) -> i32 {
+    score as i32
+}
+
+// Mate scores count plies from the root, but an entry can be found again at any ply, so
+// in the table they count from the node instead
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply
+    } else if score <= -MATE_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply
+    } else if score <= -MATE_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
+
+/// What the search has learned about quiet moves, for ordering them: two killer moves
+/// per ply that recently caused a cutoff there, a history score per side and from/to
+/// squares, and the move that last refuted each opponent move (by piece and target).
+/// Like the transposition table it is shared by every thread of a search and kept by
+/// the `Engine` between moves.
+pub struct MoveHistory {
+    killers: Vec<[AtomicU16; 2]>,
+    // Indexed by `color * 4096 + from * 64 + to`
+    butterfly: Vec<AtomicI32>,
+    // Indexed by `color * 6 + piece` of the opponent's last move, then its target square
+    countermoves: Vec<AtomicU16>,
+}
+
+impl Default for MoveHistory {
+    fn default() -> Self {
+        MoveHistory {
+            killers: (0..MAX_PLY).map(|_| [AtomicU16::new(0), AtomicU16::new(0)]).collect(),
+            butterfly: (0..2 * BOARD_SQUARES * BOARD_SQUARES).map(|_| AtomicI32::new(0)).collect(),
+            countermoves: (0..PIECE_TYPES * BOARD_SQUARES).map(|_| AtomicU16::new(0)).collect(),
+        }
+    }
+}
+
+impl MoveHistory {
+    /// Forgets the killers, which belong to one search, and halves the history scores so
+    /// recent searches count more than old ones.
+    pub fn new_search(&self) {
+        for slots in &self.killers {
+            for slot in slots {
+                slot.store(0, Ordering::Relaxed);
+            }
+        }
+        for score in &self.butterfly {
+            score.store(score.load(Ordering::Relaxed) / 2, Ordering::Relaxed);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.new_search();
+        for score in &self.butterfly {
+            score.store(0, Ordering::Relaxed);
+        }
+        for slot in &self.countermoves {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_killer(&self, ply: i32, m: Move) -> bool {
+        let Some(slots) = self.killers.get(ply as usize) else {
+            return false;
+        };
+        let packed = pack_move(Some(m));
+        slots.iter().any(|slot| slot.load(Ordering::Relaxed) == packed)
+    }
+
+    pub fn history(&self, color: Color, m: Move) -> i32 {
+        self.butterfly[Self::butterfly_index(color, m)].load(Ordering::Relaxed)
+    }
+
+    /// The move that last refuted `previous`, the opponent's move into this position.
+    pub fn countermove(&self, board: &Board, previous: Option<Move>) -> Option<Move> {
+        let index = Self::countermove_index(board, previous?)?;
+        unpack_move(self.countermoves[index].load(Ordering::Relaxed))
+    }
+
+    /// Rewards the quiet move `m` that caused a cutoff and punishes the quiet moves
+    /// `tried` before it, which didn't.
+    pub fn update(&self, board: &Board, ply: i32, depth: i32, m: Move, previous: Option<Move>, tried: &[Move]) {
+        if let Some(slots) = self.killers.get(ply as usize) {
+            let packed = pack_move(Some(m));
+            if slots[0].load(Ordering::Relaxed) != packed {
+                slots[1].store(slots[0].load(Ordering::Relaxed), Ordering::Relaxed);
+                slots[0].store(packed, Ordering::Relaxed);
+            }
+        }
+
+        let color = board.side_to_move();
+        let bonus = (depth * depth).min(MAX_HISTORY / 4);
+        self.add_history(color, m, bonus);
+        for &other in tried {
+            self.add_history(color, other, -bonus);
+        }
+
+        // `board` is the position the countermove is played in, the previous move is on it
+        if let Some(previous) = previous
+            && let Some(index) = Self::countermove_index(board, previous) {
+            self.countermoves[index].store(pack_move(Some(m)), Ordering::Relaxed);
+        }
+    }
+
+    // History gravity: the closer a score already is to +-MAX_HISTORY the less it moves,
+    // so scores stay bounded and new results can still overturn old ones
+    fn add_history(&self, color: Color, m: Move, bonus: i32) {
+        let slot = &self.butterfly[Self::butterfly_index(color, m)];
+        let score = slot.load(Ordering::Relaxed);
+        slot.store(score + bonus - score * bonus.abs() / MAX_HISTORY, Ordering::Relaxed);
+    }
+
+    fn butterfly_index(color: Color, m: Move) -> usize {
+        color as usize * BOARD_SQUARES * BOARD_SQUARES + m.from as usize * BOARD_SQUARES + m.to as usize
+    }
+
+    // Castling leaves nothing on its target square, it gets no countermove
+    fn countermove_index(board: &Board, previous: Move) -> Option<usize> {
+        let piece = board.piece_on(previous.to)?;
+        let color = board.color_on(previous.to)?;
+        Some((color as usize * 6 + piece as usize) * BOARD_SQUARES + previous.to as usize)
+    }
+}
+
+// Pieces of both colors attacking `square` when only `occupied` blocks sliders
+fn attackers_to(board: &Board, square: Square, occupied: BitBoard) -> BitBoard {
+    let rooks = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let bishops = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let pawns = (get_pawn_attacks(square, Color::Black) & board.colored_pieces(Color::White, Piece::Pawn))
+        | (get_pawn_attacks(square, Color::White) & board.colored_pieces(Color::Black, Piece::Pawn));
+
+    (pawns
+        | (get_knight_moves(square) & board.pieces(Piece::Knight))
+        | (get_king_moves(square) & board.pieces(Piece::King))
+        | (get_rook_moves(square, occupied) & rooks)
+        | (get_bishop_moves(square, occupied) & bishops))
+        & occupied
+}
+
+/// Static exchange evaluation: the material `m` wins (or loses, when negative) once
+/// both sides have made every capture on its target square that pays off for them,
+/// cheapest attacker first. X-rays through the capturing pieces are included.
+pub fn see(board: &Board, m: Move) -> i32 {
+    let target = m.to;
+    let mut occupied = board.occupied() ^ m.from.bitboard();
+    let mut on_target = board.piece_on(m.from).expect("Rust: no piece to move");
+    let mut victim = board.piece_on(target).map_or(0, |piece| PIECE_VALUES[piece as usize]);
+
+    if on_target == Piece::Pawn && m.from.file() != target.file() && !board.occupied().has(target) {
+        // En passant, the pawn taken isn't on the target square
+        occupied ^= Square::new(target.file(), m.from.rank()).bitboard();
+        victim = PIECE_VALUES[Piece::Pawn as usize];
+    }
+    if let Some(promotion) = m.promotion {
+        victim += PIECE_VALUES[promotion as usize] - PIECE_VALUES[Piece::Pawn as usize];
+        on_target = promotion;
+    }
+
+    // gains[i] is what the side making capture i has won so far if it all stops there
+    let mut gains = vec![victim];
+    let mut side = !board.side_to_move();
+    loop {
+        let attackers = attackers_to(board, target, occupied);
+        let ours = attackers & board.colors(side);
+        let Some((piece, from)) = Piece::ALL
+            .into_iter()
+            .find_map(|piece| (ours & board.pieces(piece)).next_square().map(|sq| (piece, sq))) else {
+            break;
+        };
+        // The king can only take last
+        if piece == Piece::King && !(attackers & board.colors(!side)).is_empty() {
+            break;
+        }
+
+        gains.push(PIECE_VALUES[on_target as usize] - gains[gains.len() - 1]);
+        occupied ^= from.bitboard();
+        on_target = piece;
+        side = !side;
+    }
+
+    // Either side may stop capturing when going on would cost it
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.last_mut().unwrap();
+        *previous = -(-*previous).max(last);
+    }
+    gains[0]
+}
+
+/// Material, position, pawn structure, king safety and piece activity score in centipawns,
+/// from the point of view of the side to move, which is what the negamax search works
+/// with. Every term has a middlegame and an endgame value, blended by `game_phase`.
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_with_pawns(board, &pawn_structure(board))
+}
+
+// `evaluate` with the pawn structure already worked out, usually by the pawn table
+fn evaluate_with_pawns(board: &Board, pawns: &PawnStructure) -> i32 {
+    // Middlegame and endgame scores, from White's point of view
+    let mut middlegame = pawns.middlegame;
+    let mut endgame = pawns.endgame;
+    let (mg, eg) = passed_pawns(board, pawns.passed);
+    middlegame += mg;
+    endgame += eg;
+    middlegame += king_safety(board);
+    let (mg, eg) = piece_activity(board);
+    middlegame += mg;
+    endgame += eg;
+    for &piece in &Piece::ALL {
+        for color in [Color::White, Color::Black] {
+            let sign = if color == Color::White { 1 } else { -1 };
+            for square in board.colored_pieces(color, piece) {
+                let (mg, eg) = piece_square(piece, color, square);
+                middlegame += sign * mg;
+                endgame += sign * eg;
+            }
+        }
+    }
+
+    let score = taper(middlegame, endgame, game_phase(board));
+    if board.side_to_move() == Color::White { score } else { -score }
+}
+
+/// Middlegame and endgame value of a `piece` of `color` on `square`, material included.
+pub fn piece_square(piece: Piece, color: Color, square: Square) -> (i32, i32) {
+    // The tables are drawn from White's side, a8 first. Black's pieces see them mirrored.
+    let index = match color {
+        Color::White => square.flip_rank() as usize,
+        Color::Black => square as usize,
+    };
+    let piece = piece as usize;
+    (
+        MIDDLEGAME_VALUES[piece] + MIDDLEGAME_TABLES[piece][index],
+        ENDGAME_VALUES[piece] + ENDGAME_TABLES[piece][index],
+    )
+}
+
+/// How far the game is from the endgame, computed from the pieces left: `MAX_PHASE`
+/// with all of them on the board, down to 0 with only kings and pawns. Extra pieces from
+/// promotions don't count past `MAX_PHASE`.
+pub fn game_phase(board: &Board) -> i32 {
+    let phase: i32 = Piece::ALL
+        .iter()
+        .zip(PHASE_WEIGHTS)
+        .map(|(&piece, weight)| board.pieces(piece).len() as i32 * weight)
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// Blends a middlegame and an endgame score by `phase`, all middlegame at `MAX_PHASE`
+fn taper(middlegame: i32, endgame: i32, phase: i32) -> i32 {
+    (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// Every square on the ranks in front of `rank`, as `color` sees the board
+fn ranks_ahead(color: Color, rank: Rank) -> BitBoard {
+    Rank::ALL
+        .iter()
+        .filter(|r| r.relative_to(color) > rank.relative_to(color))
+        .fold(BitBoard::EMPTY, |ahead, r| ahead | r.bitboard())
+}
+
+// Where an enemy pawn would stop a pawn of `color` on `square` from being passed: in
+// front of it, on its own file and the ones next to it
+fn passed_pawn_mask(color: Color, square: Square) -> BitBoard {
+    ranks_ahead(color, square.rank()) & (square.file().bitboard() | square.file().adjacent())
+}
+
+/// What only depends on the pawns: the structure score from White's point of view, and
+/// which pawns are passed. Kept in the `PawnTable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PawnStructure {
+    pub middlegame: i32,
+    pub endgame: i32,
+    pub passed: BitBoard,
+}
+
+/// Scores doubled, isolated, backward and connected pawns, and finds the passed ones.
+pub fn pawn_structure(board: &Board) -> PawnStructure {
+    let mut structure = PawnStructure {
+        middlegame: 0,
+        endgame: 0,
+        passed: BitBoard::EMPTY,
+    };
+
+    for color in [Color::White, Color::Black] {
+        let sign = if color == Color::White { 1 } else { -1 };
+        let ours = board.colored_pieces(color, Piece::Pawn);
+        let theirs = board.colored_pieces(!color, Piece::Pawn);
+
+        for square in ours {
+            let file = square.file();
+            let rank = square.rank().relative_to(color) as usize;
+            let neighbours = ours & file.adjacent();
+            let mut mg = 0;
+            let mut eg = 0;
+
+            // Another of our pawns in front on the same file
+            if !(ours & file.bitboard() & ranks_ahead(color, square.rank())).is_empty() {
+                mg += DOUBLED_PAWN.0;
+                eg += DOUBLED_PAWN.1;
+            }
+
+            // Defended by a pawn, or side by side with one
+            let supporters = get_pawn_attacks(square, !color) & ours;
+            let phalanx = neighbours & square.rank().bitboard();
+            if neighbours.is_empty() {
+                mg += ISOLATED_PAWN.0;
+                eg += ISOLATED_PAWN.1;
+            } else if !(supporters | phalanx).is_empty() {
+                mg += CONNECTED_PAWN[rank];
+                eg += CONNECTED_PAWN[rank] / 2;
+            } else if (neighbours & !ranks_ahead(color, square.rank())).is_empty() {
+                // Every neighbour has gone past it, and an enemy pawn guards the square
+                // in front, so it can neither advance safely nor be defended
+                if let Some(stop) = square.try_offset(0, if color == Color::White { 1 } else { -1 })
+                    && !(get_pawn_attacks(stop, color) & theirs).is_empty() {
+                    mg += BACKWARD_PAWN.0;
+                    eg += BACKWARD_PAWN.1;
+                }
+            }
+
+            if (theirs & passed_pawn_mask(color, square)).is_empty() {
+                structure.passed |= square.bitboard();
+            }
+
+            structure.middlegame += sign * mg;
+            structure.endgame += sign * eg;
+        }
+    }
+    structure
+}
+
+// Middlegame and endgame bonus of the `passed` pawns from White's point of view. Worth
+// more the further they are, less when something stands in their way, and a lot more
+// in a pawn ending with the enemy king outside of the pawn's square, too far away to
+// catch it.
+fn passed_pawns(board: &Board, passed: BitBoard) -> (i32, i32) {
+    let occupied = board.occupied();
+    let mut middlegame = 0;
+    let mut endgame = 0;
+
+    for color in [Color::White, Color::Black] {
+        let sign = if color == Color::White { 1 } else { -1 };
+        let their_king = board.king(!color);
+        let their_pieces = board.colors(!color) & !(board.pieces(Piece::Pawn) | board.pieces(Piece::King));
+
+        for square in passed & board.colors(color) {
+            let rank = square.rank().relative_to(color) as usize;
+            let mut mg = PASSED_PAWN[rank].0;
+            let mut eg = PASSED_PAWN[rank].1;
+
+            let path = square.file().bitboard() & ranks_ahead(color, square.rank());
+            if !(path & occupied).is_empty() {
+                mg /= 2;
+                eg /= 2;
+            }
+
+            if their_pieces.is_empty() {
+                let promotion = Square::new(square.file(), Rank::Eighth.relative_to(color));
+                // From its starting rank the first step can be a double one
+                let moves_to_go = (7 - rank as i32).min(5);
+                let tempo = (board.side_to_move() != color) as i32;
+                if king_distance(their_king, promotion) - tempo > moves_to_go {
+                    eg += UNSTOPPABLE_PASSED_PAWN;
+                }
+            }
+
+            middlegame += sign * mg;
+            endgame += sign * eg;
+        }
+    }
+    (middlegame, endgame)
+}
+
+// King moves from one square to the other on an empty board
+fn king_distance(a: Square, b: Square) -> i32 {
+    let files = (a.file() as i32 - b.file() as i32).abs();
+    let ranks = (a.rank() as i32 - b.rank() as i32).abs();
+    files.max(ranks)
+}
+
+// Squares a `piece` of `color` on `square` attacks, with `occupied` in the way of sliders
+fn piece_attacks(piece: Piece, color: Color, square: Square, occupied: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Pawn => get_pawn_attacks(square, color),
+        Piece::Knight => get_knight_moves(square),
+        Piece::Bishop => get_bishop_moves(square, occupied),
+        Piece::Rook => get_rook_moves(square, occupied),
+        Piece::Queen => get_bishop_moves(square, occupied) | get_rook_moves(square, occupied),
+        Piece::King => get_king_moves(square),
+    }
+}
+
+// Every square `color` attacks
+fn attacked_by(board: &Board, color: Color) -> BitBoard {
+    let occupied = board.occupied();
+    let mut attacked = BitBoard::EMPTY;
+    for &piece in &Piece::ALL {
+        for square in board.colored_pieces(color, piece) {
+            attacked |= piece_attacks(piece, color, square, occupied);
+        }
+    }
+    attacked
+}
+
+/// Middlegame king safety from White's point of view. The endgame has none, there the
+/// king is a fighting piece.
+pub fn king_safety(board: &Board) -> i32 {
+    [Color::White, Color::Black]
+        .into_iter()
+        .map(|color| {
+            let sign = if color == Color::White { 1 } else { -1 };
+            sign * (king_shelter(board, color) - king_danger(board, color))
+        })
+        .sum()
+}
+
+// The pawns on the king's file and the ones next to it: our own close in front shelter
+// it, missing ones leave a (half) open file, and enemy ones coming up are a storm
+fn king_shelter(board: &Board, color: Color) -> i32 {
+    let king = board.king(color);
+    let ours = board.colored_pieces(color, Piece::Pawn);
+    let theirs = board.colored_pieces(!color, Piece::Pawn);
+    let in_front = ranks_ahead(color, king.rank());
+    let king_rank = king.rank().relative_to(color) as i32;
+    // How many ranks in front of the king the closest pawn of `pawns` on `file` is
+    let closest = |pawns: BitBoard, file: File| {
+        (pawns & file.bitboard() & in_front)
+            .iter()
+            .map(|square| square.rank().relative_to(color) as i32 - king_rank)
+            .min()
+    };
+
+    let mut score = 0;
+    let king_file = king.file() as usize;
+    for &file in &File::ALL[king_file.saturating_sub(1)..=(king_file + 1).min(7)] {
+        let shield = closest(ours, file);
+        match shield {
+            Some(distance) if distance <= 2 => score += PAWN_SHIELD[distance as usize],
+            Some(_) => {}
+            None if (theirs & file.bitboard()).is_empty() => score += OPEN_FILE_NEAR_KING,
+            None => score += SEMI_OPEN_FILE_NEAR_KING,
+        }
+
+        if let Some(distance) = closest(theirs, file)
+            && distance < PAWN_STORM.len() as i32 {
+            // Stuck right in front of our pawn it can't open anything
+            let blocked = shield == Some(distance - 1);
+            score -= if blocked { PAWN_STORM[distance as usize] / 2 } else { PAWN_STORM[distance as usize] };
+        }
+    }
+    score
+}
+
+// How hard the enemy pieces attack the squares around the king, growing with the number
+// of attackers, plus the safe checks they have: checks from a square we don't defend
+fn king_danger(board: &Board, color: Color) -> i32 {
+    let king = board.king(color);
+    let zone = get_king_moves(king) | king.bitboard();
+    let occupied = board.occupied();
+    let them = !color;
+    let safe = !attacked_by(board, color) & !board.colors(them);
+
+    let mut attackers = 0;
+    let mut attack_weight = 0;
+    let mut checks = 0;
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        // Where this kind of piece would give check from
+        let checking = piece_attacks(piece, color, king, occupied);
+        for square in board.colored_pieces(them, piece) {
+            let attacks = piece_attacks(piece, them, square, occupied);
+            let hits = (attacks & zone).len() as i32;
+            if hits > 0 {
+                attackers += 1;
+                attack_weight += KING_ATTACK_WEIGHT[piece as usize] * hits;
+            }
+            if !(attacks & checking & safe).is_empty() {
+                checks += SAFE_CHECK[piece as usize];
+            }
+        }
+    }
+    attack_weight * KING_ATTACKERS_SCALE[attackers.min(KING_ATTACKERS_SCALE.len() - 1)] / 100 + checks
+}
+
+/// Mobility and placement of the pieces, middlegame and endgame from White's point of
+/// view: the safe squares they can go to, the bishop pair, rooks on open files and the
+/// seventh rank, knight outposts, and bishops and rooks that got themselves trapped.
+pub fn piece_activity(board: &Board) -> (i32, i32) {
+    let occupied = board.occupied();
+    let mut middlegame = 0;
+    let mut endgame = 0;
+
+    for color in [Color::White, Color::Black] {
+        let sign = if color == Color::White { 1 } else { -1 };
+        let ours = board.colored_pieces(color, Piece::Pawn);
+        let theirs = board.colored_pieces(!color, Piece::Pawn);
+        let king = board.king(color);
+        // A square an enemy pawn guards is no place for a piece, nor one we already stand on
+        let pawn_attacks = theirs
+            .iter()
+            .fold(BitBoard::EMPTY, |attacked, square| attacked | get_pawn_attacks(square, !color));
+        let safe = !(board.colors(color) | pawn_attacks);
+        let mut mg = 0;
+        let mut eg = 0;
+
+        let bishops = board.colored_pieces(color, Piece::Bishop);
+        if !(bishops & BitBoard::DARK_SQUARES).is_empty() && !(bishops & BitBoard::LIGHT_SQUARES).is_empty() {
+            mg += BISHOP_PAIR.0;
+            eg += BISHOP_PAIR.1;
+        }
+
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for square in board.colored_pieces(color, piece) {
+                let mobility = (piece_attacks(piece, color, square, occupied) & safe).len() as i32;
+                let rank = square.rank().relative_to(color);
+                mg += (mobility - MOBILITY_BASELINE[piece as usize]) * MOBILITY[piece as usize].0;
+                eg += (mobility - MOBILITY_BASELINE[piece as usize]) * MOBILITY[piece as usize].1;
+
+                match piece {
+                    Piece::Knight => {
+                        // Defended by a pawn, where no enemy pawn can ever chase it away
+                        let supported = !(get_pawn_attacks(square, !color) & ours).is_empty();
+                        let chasers = theirs & passed_pawn_mask(color, square) & square.file().adjacent();
+                        if (Rank::Fourth..=Rank::Sixth).contains(&rank) && supported && chasers.is_empty() {
+                            mg += KNIGHT_OUTPOST.0;
+                            eg += KNIGHT_OUTPOST.1;
+                        }
+                    }
+                    Piece::Bishop => {
+                        // Took the a7 (or h7) pawn and got shut in by b6 (or g6)
+                        let blocker = match square.file() {
+                            File::A => Some(File::B),
+                            File::H => Some(File::G),
+                            _ => None,
+                        };
+                        if rank == Rank::Seventh
+                            && let Some(file) = blocker
+                            && theirs.has(Square::new(file, Rank::Sixth.relative_to(color))) {
+                            mg += TRAPPED_BISHOP.0;
+                            eg += TRAPPED_BISHOP.1;
+                        }
+                    }
+                    Piece::Rook => {
+                        let file = square.file().bitboard();
+                        if (ours & file).is_empty() {
+                            let (open_mg, open_eg) = if (theirs & file).is_empty() { ROOK_OPEN_FILE } else { ROOK_SEMI_OPEN_FILE };
+                            mg += open_mg;
+                            eg += open_eg;
+                        }
+
+                        // Only worth it with pawns to eat there or the king cut off behind
+                        let seventh = Rank::Seventh.relative_to(color).bitboard();
+                        if rank == Rank::Seventh
+                            && (!(theirs & seventh).is_empty() || board.king(!color).rank().relative_to(color) == Rank::Eighth) {
+                            mg += ROOK_ON_SEVENTH.0;
+                            eg += ROOK_ON_SEVENTH.1;
+                        }
+
+                        // Boxed in on the back rank by a king that can no longer castle out of its way
+                        let rights = board.castle_rights(color);
+                        let boxed_in = if king.file() >= File::E {
+                            square.file() > king.file() && rights.short.is_none()
+                        } else {
+                            square.file() < king.file() && rights.long.is_none()
+                        };
+                        if mobility <= TRAPPED_ROOK_MOBILITY && rank == Rank::First && king.rank() == square.rank() && boxed_in {
+                            mg += TRAPPED_ROOK.0;
+                            eg += TRAPPED_ROOK.1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        middlegame += sign * mg;
+        endgame += sign * eg;
+    }
+    (middlegame, endgame)
+}
+
+/// Pawn structures by pawn key, so the search doesn't work out the same one over and
+/// over. Each entry is three words: the key xor-ed with the other two, the packed scores,
+/// and the passed pawns. A read that finds the words out of step, because a thread
+/// wrote in between, doesn't match the key and counts as a miss.
+pub struct PawnTable {
+    entries: Vec<[AtomicU64; 3]>,
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new(PAWN_TABLE_ENTRIES)
+    }
+}
+
+impl PawnTable {
+    pub fn new(entries: usize) -> Self {
+        PawnTable {
+            entries: (0..entries.max(1)).map(|_| [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)]).collect(),
+        }
+    }
+
+    fn slot(&self, key: u64) -> &[AtomicU64; 3] {
+        &self.entries[((key as u128 * self.entries.len() as u128) >> 64) as usize]
+    }
+
+    pub fn get(&self, key: u64) -> Option<PawnStructure> {
+        let [check, scores, passed] = self.slot(key).each_ref().map(|word| word.load(Ordering::Relaxed));
+        if check ^ scores ^ passed != key {
+            return None;
+        }
+        Some(PawnStructure {
+            middlegame: scores as u32 as i32,
+            endgame: (scores >> 32) as u32 as i32,
+            passed: BitBoard(passed),
+        })
+    }
+
+    pub fn store(&self, key: u64, pawns: &PawnStructure) {
+        let scores = pawns.middlegame as u32 as u64 | (pawns.endgame as u32 as u64) << 32;
+        let [check, packed, passed] = self.slot(key);
+        check.store(key ^ scores ^ pawns.passed.0, Ordering::Relaxed);
+        packed.store(scores, Ordering::Relaxed);
+        passed.store(pawns.passed.0, Ordering::Relaxed);
+    }
+}
+
+// Pawn structure terms, middlegame and endgame, in centipawns. The rank ones are indexed
+// by rank as the pawn's owner sees the board.
+const DOUBLED_PAWN: (i32, i32) = (-10, -25);
+const ISOLATED_PAWN: (i32, i32) = (-12, -15);
+const BACKWARD_PAWN: (i32, i32) = (-8, -12);
+const CONNECTED_PAWN: [i32; 8] = [0, 4, 8, 12, 20, 35, 60, 0];
+const PASSED_PAWN: [(i32, i32); 8] = [(0, 0), (2, 8), (5, 12), (10, 20), (20, 40), (35, 70), (60, 110), (0, 0)];
+const UNSTOPPABLE_PASSED_PAWN: i32 = 400;
+// King safety, middlegame only. Shelter by how many ranks in front of the king our
+// closest pawn is, storms by how close theirs are; the rest per piece type.
+const PAWN_SHIELD: [i32; 3] = [0, 20, 10];
+const PAWN_STORM: [i32; 4] = [0, 5, 25, 10];
+const SEMI_OPEN_FILE_NEAR_KING: i32 = -15;
+const OPEN_FILE_NEAR_KING: i32 = -25;
+// Per attacked square around the king
+const KING_ATTACK_WEIGHT: [i32; 6] = [0, 10, 10, 15, 25, 0];
+// Percent of the attack weight that counts, by number of attackers: one alone is harmless
+const KING_ATTACKERS_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+const SAFE_CHECK: [i32; 6] = [0, 30, 20, 35, 30, 0];
+// Piece activity, middlegame and endgame. Mobility is per safe square more or less than
+// what the piece usually has, in `Piece` order.
+const MOBILITY: [(i32, i32); 6] = [(0, 0), (4, 4), (5, 5), (2, 4), (1, 2), (0, 0)];
+const MOBILITY_BASELINE: [i32; 6] = [0, 4, 6, 6, 12, 0];
+const BISHOP_PAIR: (i32, i32) = (30, 50);
+const ROOK_OPEN_FILE: (i32, i32) = (40, 15);
+const ROOK_SEMI_OPEN_FILE: (i32, i32) = (20, 8);
+const ROOK_ON_SEVENTH: (i32, i32) = (20, 40);
+const KNIGHT_OUTPOST: (i32, i32) = (25, 15);
+const TRAPPED_BISHOP: (i32, i32) = (-100, -100);
+const TRAPPED_ROOK: (i32, i32) = (-50, -20);
+// At most this many safe squares for a rook to count as trapped
+const TRAPPED_ROOK_MOBILITY: i32 = 3;
+// Entries in a pawn table, three words each
+const PAWN_TABLE_ENTRIES: usize = 1 << 14;
+
+// Material and piece-square tables in centipawns, from PeSTO (Ronald Friederich's
+// Rofchade), in `Piece` order
+const MIDDLEGAME_VALUES: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+const ENDGAME_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+#[rustfmt::skip]
+const MIDDLEGAME_TABLES: [[i32; 64]; 6] = [
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         98, 134,  61,  95,  68, 126,  34, -11,
+         -6,   7,  26,  31,  65,  56,  25, -20,
+        -14,  13,   6,  21,  23,  12,  17, -23,
+        -27,  -2,  -5,  12,  17,   6,  10, -25,
+        -26,  -4,  -4, -10,   3,   3,  33, -12,
+        -35,  -1, -20, -23, -15,  24,  38, -22,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // Knight
+    [
+       -167, -89, -34, -49,  61, -97, -15,-107,
+        -73, -41,  72,  36,  23,  62,   7, -17,
+        -47,  60,  37,  65,  84, 129,  73,  44,
+         -9,  17,  19,  53,  37,  69,  18,  22,
+        -13,   4,  16,  13,  28,  19,  21,  -8,
+        -23,  -9,  12,  10,  19,  17,  25, -16,
+        -29, -53, -12,  -3,  -1,  18, -14, -19,
+       -105, -21, -58, -33, -17, -28, -19, -23,
+    ],
+    // Bishop
+    [
+        -29,   4, -82, -37, -25, -42,   7,  -8,
+        -26,  16, -18, -13,  30,  59,  18, -47,
+        -16,  37,  43,  40,  35,  50,  37,  -2,
+         -4,   5,  19,  50,  37,  37,   7,  -2,
+         -6,  13,  13,  26,  34,  12,  10,   4,
+          0,  15,  15,  15,  14,  27,  18,  10,
+          4,  15,  16,   0,   7,  21,  33,   1,
+        -33,  -3, -14, -21, -13, -12, -39, -21,
+    ],
+    // Rook
+    [
+         32,  42,  32,  51,  63,   9,  31,  43,
+         27,  32,  58,  62,  80,  67,  26,  44,
+         -5,  19,  26,  36,  17,  45,  61,  16,
+        -24, -11,   7,  26,  24,  35,  -8, -20,
+        -36, -26, -12,  -1,   9,  -7,   6, -23,
+        -45, -25, -16, -17,   3,   0,  -5, -33,
+        -44, -16, -20,  -9,  -1,  11,  -6, -71,
+        -19, -13,   1,  17,  16,   7, -37, -26,
+    ],
+    // Queen
+    [
+        -28,   0,  29,  12,  59,  44,  43,  45,
+        -24, -39,  -5,   1, -16,  57,  28,  54,
+        -13, -17,   7,   8,  29,  56,  47,  57,
+        -27, -27, -16, -16,  -1,  17,  -2,   1,
+         -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+        -14,   2, -11,  -2,  -5,   2,  14,   5,
+        -35,  -8,  11,   2,   8,  15,  -3,   1,
+         -1, -18,  -9,  10, -15, -25, -31, -50,
+    ],
+    // King
+    [
+        -65,  23,  16, -15, -56, -34,   2,  13,
+         29,  -1, -20,  -7,  -8,  -4, -38, -29,
+         -9,  24,   2, -16, -20,   6,  22, -22,
+        -17, -20, -12, -27, -30, -25, -14, -36,
+        -49,  -1, -27, -39, -46, -44, -33, -51,
+        -14, -14, -22, -46, -44, -30, -15, -27,
+          1,   7,  -8, -64, -43, -16,   9,   8,
+        -15,  36,  12, -54,   8, -28,  24,  14,
+    ],
+];
+
+#[rustfmt::skip]
+const ENDGAME_TABLES: [[i32; 64]; 6] = [
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+        178, 173, 158, 134, 147, 132, 165, 187,
+         94, 100,  85,  67,  56,  53,  82,  84,
+         32,  24,  13,   5,  -2,   4,  17,  17,
+         13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+          4,   7,  -6,   1,   0,  -5,  -1,  -8,
+         13,   8,   8,  10,  13,   0,   2,  -7,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // Knight
+    [
+        -58, -38, -13, -28, -31, -27, -63, -99,
+        -25,  -8, -25,  -2,  -9, -25, -24, -52,
+        -24, -20,  10,   9,  -1,  -9, -19, -41,
+        -17,   3,  22,  22,  22,  11,   8, -18,
+        -18,  -6,  16,  25,  16,  17,   4, -18,
+        -23,  -3,  -1,  15,  10,  -3, -20, -22,
+        -42, -20, -10,  -5,  -2, -20, -23, -44,
+        -29, -51, -23, -15, -22, -18, -50, -64,
+    ],
+    // Bishop
+    [
+        -14, -21, -11,  -8,  -7,  -9, -17, -24,
+         -8,  -4,   7, -12,  -3, -13,  -4, -14,
+          2,  -8,   0,  -1,  -2,   6,   0,   4,
+         -3,   9,  12,   9,  14,  10,   3,   2,
+         -6,   3,  13,  19,   7,  10,  -3,  -9,
+        -12,  -3,   8,  10,  13,   3,  -7, -15,
+        -14, -18,  -7,  -1,   4,  -9, -15, -27,
+        -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+    ],
+    // Rook
+    [
+         13,  10,  18,  15,  12,  12,   8,   5,
+         11,  13,  13,  11,  -3,   3,   8,   3,
+          7,   7,   7,   5,   4,  -3,  -5,  -3,
+          4,   3,  13,   1,   2,   1,  -1,   2,
+          3,   5,   8,   4,  -5,  -6,  -8, -11,
+         -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+         -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+         -9,   2,   3,  -1,  -5, -13,   4, -20,
+    ],
+    // Queen
+    [
+         -9,  22,  22,  27,  27,  19,  10,  20,
+        -17,  20,  32,  41,  58,  25,  30,   0,
+        -20,   6,   9,  49,  47,  35,  19,   9,
+          3,  22,  24,  45,  57,  40,  57,  36,
+        -18,  28,  19,  47,  31,  34,  39,  23,
+        -16, -27,  15,   6,   9,  17,  10,   5,
+        -22, -23, -30, -16, -16, -23, -36, -32,
+        -33, -28, -22, -43,  -5, -32, -20, -41,
+    ],
+    // King
+    [
+        -74, -35, -18, -18, -11,  15,   4, -17,
+        -12,  17,  14,  17,  17,  38,  23,  11,
+         10,  17,  23,  15,  20,  45,  44,  13,
+         -8,  22,  24,  27,  26,  33,  26,   3,
+        -18,  -4,  21,  24,  27,  23,   9, -11,
+        -19,  -3,  11,  21,  23,  16,   7,  -9,
+        -27, -11,   4,  13,  14,   4,  -5, -17,
+        -53, -34, -21, -11, -28, -14, -24, -43,
+    ],
+];
 
 #[pymodule]
-fn chessbot(py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(pyo3::wrap_pyfunction!(update_FEN, m)?)?;
+fn chessbot(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(pyo3::wrap_pyfunction!(update_fen, m)?)?;
     m.add_function(pyo3::wrap_pyfunction!(find_best_move, m)?)?;
+    m.add_function(pyo3::wrap_pyfunction!(stop, m)?)?;
+    m.add_class::<Engine>()?;
     Ok(())
 }
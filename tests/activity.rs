@@ -0,0 +1,61 @@
+use chessbot::piece_activity;
+use cozy_chess::Board;
+
+fn activity(fen: &str) -> (i32, i32) {
+    piece_activity(&Board::from_fen(fen, false).unwrap())
+}
+
+#[test]
+fn squares_covered_by_enemy_pawns_dont_count() {
+    // The d7 pawn takes c6 and e6 away from the knight, the h7 one nothing
+    let free = activity("4k3/7p/8/8/3N4/8/8/4K3 w - - 0 1");
+    let covered = activity("4k3/3p4/8/8/3N4/8/8/4K3 w - - 0 1");
+    assert!(covered.0 < free.0, "{:?} vs {:?}", covered, free);
+
+    // A knight in the corner has next to nothing
+    let cornered = activity("4k3/7p/8/8/8/8/8/N3K3 w - - 0 1");
+    assert!(cornered.0 < free.0, "{:?} vs {:?}", cornered, free);
+}
+
+#[test]
+fn bishops_on_both_colours_are_a_pair() {
+    // Seven squares for each bishop in both, but a1 and c1 are both dark
+    let pair = activity("B3k3/8/8/8/8/8/8/B3K3 w - - 0 1");
+    let same = activity("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1");
+    assert!(pair.0 > same.0 && pair.1 > same.1, "{:?} vs {:?}", pair, same);
+}
+
+#[test]
+fn rooks_want_open_files_and_the_seventh() {
+    let open = activity("4k3/8/8/8/8/8/6P1/3RK3 w - - 0 1");
+    let semi_open = activity("4k3/3p4/8/8/8/8/6P1/3RK3 w - - 0 1");
+    let closed = activity("4k3/3p4/8/8/8/8/3P4/3RK3 w - - 0 1");
+    assert!(open.0 > semi_open.0, "{:?} vs {:?}", open, semi_open);
+    assert!(semi_open.0 > closed.0, "{:?} vs {:?}", semi_open, closed);
+
+    // Fourteen squares either way, but on the seventh the king is cut off on the last rank
+    let seventh = activity("7k/R7/8/8/8/8/8/7K w - - 0 1");
+    let sixth = activity("7k/8/R7/8/8/8/8/7K w - - 0 1");
+    assert!(seventh.1 > sixth.1, "{:?} vs {:?}", seventh, sixth);
+}
+
+#[test]
+fn knights_like_outposts() {
+    // The e4 pawn defends the knight. The c7 pawn could chase it off, the a7 one can't.
+    let outpost = activity("4k3/p7/8/3N4/4P3/8/8/4K3 w - - 0 1");
+    let chased = activity("4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1");
+    assert!(outpost.0 > chased.0, "{:?} vs {:?}", outpost, chased);
+}
+
+#[test]
+fn trapped_pieces_cost() {
+    // The bishop took on a7 and b6 shut it in
+    let trapped = activity("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1");
+    let free = activity("4k3/B7/7p/8/8/8/8/4K3 w - - 0 1");
+    assert!(trapped.0 < free.0 - 50, "{:?} vs {:?}", trapped, free);
+
+    // The same rook on h1, boxed in once the king can no longer castle
+    let castling = activity("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1");
+    let boxed_in = activity("4k3/8/8/8/8/8/5PPP/4K2R w - - 0 1");
+    assert!(boxed_in.0 < castling.0, "{:?} vs {:?}", boxed_in, castling);
+}
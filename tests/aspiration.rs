@@ -0,0 +1,26 @@
+mod common;
+
+use common::search;
+
+// Tactics whose score swings between iterations by more than the window, up as well
+// as down, so both re-searches get run
+const SWINGING: [(&str, i32); 2] = [
+    ("r1bqkb1r/pppp1ppp/5n2/4n3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 4", 6),
+    ("r3k3/ppp2ppp/8/3N4/8/8/PPP2PPP/4K3 w - - 0 1", 7),
+];
+
+#[test]
+fn windows_find_what_a_full_window_finds() {
+    let (mut fail_lows, mut fail_highs) = (0, 0);
+    for (fen, depth) in SWINGING {
+        let windowed = search(fen, depth, &[("Aspiration", "true")]);
+        let full = search(fen, depth, &[("Aspiration", "false")]);
+        assert_eq!(windowed.score, full.score, "{}", fen);
+        assert_eq!(windowed.pv[0], full.pv[0], "{}", fen);
+        assert_eq!((full.fail_lows, full.fail_highs), (0, 0), "{}", fen);
+        fail_lows += windowed.fail_lows;
+        fail_highs += windowed.fail_highs;
+    }
+    // Otherwise neither re-search was exercised
+    assert!(fail_lows > 0 && fail_highs > 0, "{} fail lows, {} fail highs", fail_lows, fail_highs);
+}
@@ -0,0 +1,23 @@
+use chessbot::{best_move_uci, play_uci_move};
+use cozy_chess::Color;
+
+#[test]
+fn castling_from_find_best_move_is_accepted_by_update_fen() {
+    // Only O-O-O mates, the king on c1 takes c2 away from the black king
+    let fen = "8/8/8/8/2ppp3/2pkp3/8/R3K1N1 w Q - 0 1";
+    let best = best_move_uci(fen, 60_000, true, Color::White, &[]).unwrap();
+    assert_eq!(best, "e1c1");
+
+    let after = play_uci_move(fen, &best).unwrap();
+    assert_eq!(after.split_whitespace().next(), Some("8/8/8/8/2ppp3/2pkp3/8/2KR2N1"));
+    // The moves played since the FEN take it too, and the game is over after it
+    assert_eq!(best_move_uci(fen, 60_000, true, Color::White, &[best]).unwrap(), "END");
+}
+
+#[test]
+fn bad_input_is_an_error() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(play_uci_move("not a fen", "e2e4"), Err("Rust: bad FEN string".to_string()));
+    assert_eq!(play_uci_move(start, "e2"), Err("Rust: invalid move".to_string()));
+    assert_eq!(play_uci_move(start, "e2e5"), Err("Rust: illegal move".to_string()));
+}
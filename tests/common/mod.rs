@@ -0,0 +1,61 @@
+// Every test file builds its own copy of this module and only uses part of it
+#![allow(dead_code)]
+
+use chessbot::{Engine, SearchInfo, SearchLimits};
+use cozy_chess::Move;
+
+// A fresh engine on `fen` with `options` applied, ready to search
+pub fn engine(fen: &str, options: &[(&str, &str)]) -> Engine {
+    let mut engine = Engine::default();
+    for (name, value) in options {
+        engine.apply_option(name, value).unwrap();
+    }
+    engine.set_position(Some(fen)).unwrap();
+    engine.clear_stop();
+    engine
+}
+
+// Best move of a fixed depth search from the engine's current position
+pub fn search_depth(engine: &Engine, depth: i32) -> Option<Move> {
+    engine.clear_stop();
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+    engine.search(limits, None)
+}
+
+// What a fixed depth search of `fen` found at its last depth, on a fresh engine with
+// `options` applied
+pub fn search(fen: &str, depth: i32, options: &[(&str, &str)]) -> SearchInfo {
+    let engine = engine(fen, options);
+    search_depth(&engine, depth).unwrap();
+    engine.last_info().unwrap()
+}
+
+// The same position with the colours swapped: ranks mirrored, pieces, side to move,
+// castling rights and the en passant square all switched over
+pub fn flip(fen: &str) -> String {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let swap_case = |s: &str| -> String {
+        s.chars()
+            .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+            .collect()
+    };
+    let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+    let side = if fields[1] == "w" { "b" } else { "w" };
+    let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+    castling.sort_by_key(|c| (c.is_ascii_lowercase(), *c));
+    let en_passant = if fields[3] == "-" { "-".to_string() } else { flip_move(fields[3]) };
+    format!("{} {} {} {} {} {}", placement.join("/"), side, castling.into_iter().collect::<String>(), en_passant, fields[4], fields[5])
+}
+
+// A UCI move or square as it reads on the flipped board
+pub fn flip_move(mv: &str) -> String {
+    mv.chars()
+        .map(|c| match c.to_digit(10) {
+            Some(rank) => char::from_digit(9 - rank, 10).unwrap(),
+            None => c,
+        })
+        .collect()
+}
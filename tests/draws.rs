@@ -0,0 +1,73 @@
+mod common;
+
+use chessbot::{Engine, Position};
+use cozy_chess::{Board, Move};
+
+const SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+fn moves(uci: &[&str]) -> Vec<Move> {
+    uci.iter().map(|m| m.parse().unwrap()).collect()
+}
+
+// Best move and score of a fixed depth search from the engine's current position
+fn search(engine: &Engine, depth: i32) -> (Move, i32) {
+    let best = common::search_depth(engine, depth).unwrap();
+    (best, engine.last_info().unwrap().score)
+}
+
+#[test]
+fn repetition_inside_the_search_is_a_draw() {
+    let mut position = Position::new(Board::default());
+    for m in moves(&SHUFFLE) {
+        assert!(!position.is_repetition());
+        position.make_move(m);
+    }
+    assert!(position.is_repetition());
+}
+
+#[test]
+fn game_history_needs_threefold() {
+    let once = Position::with_history(Board::default(), &moves(&SHUFFLE));
+    assert!(!once.is_repetition());
+
+    let twice = Position::with_history(Board::default(), &moves(&[SHUFFLE, SHUFFLE].concat()));
+    assert!(twice.is_repetition());
+
+    // A pawn move in between means nothing before it can come back
+    let reset = [&SHUFFLE[..], &["e2e4", "e7e5"], &SHUFFLE[..]].concat();
+    let mut position = Position::with_history(Board::default(), &moves(&reset));
+    for m in moves(&SHUFFLE) {
+        position.make_move(m);
+    }
+    assert!(position.is_repetition());
+    assert!(!Position::with_history(Board::default(), &moves(&reset)).is_repetition());
+}
+
+#[test]
+fn engine_takes_a_threefold_it_likes() {
+    // Black can repeat the start position a third time with f6g8
+    let history = [&SHUFFLE[..], &SHUFFLE[..3]].concat();
+    let mut engine = common::engine(&Board::default().to_string(), &[("Contempt", "-500")]);
+    for m in &history {
+        engine.play_move(m).unwrap();
+    }
+    assert_eq!(search(&engine, 2), ("f6g8".parse().unwrap(), 500));
+
+    // Without the history there is nothing to repeat
+    let fen = engine.board().to_string();
+    engine.set_position(Some(&fen)).unwrap();
+    assert_ne!(search(&engine, 2).1, 500);
+}
+
+#[test]
+fn fifty_move_rule_is_a_draw() {
+    // A queen up, but every move is quiet and reaches the hundredth half-move
+    let mut engine = common::engine("7k/8/2p5/8/8/8/Q7/7K w - - 99 80", &[]);
+    assert_eq!(search(&engine, 3).1, 0);
+
+    engine.apply_option("Contempt", "50").unwrap();
+    assert_eq!(search(&engine, 3).1, -50);
+
+    engine.set_position(Some("7k/8/2p5/8/8/8/Q7/7K w - - 0 80")).unwrap();
+    assert!(search(&engine, 3).1 > 0);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use chessbot::{evaluate, game_phase, MAX_PHASE};
+use common::flip;
+use cozy_chess::{Board, Color};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn board(fen: &str) -> Board {
+    Board::from_fen(fen, false).unwrap()
+}
+
+// From White's point of view instead of the side to move's
+fn white_eval(board: &Board) -> i32 {
+    if board.side_to_move() == Color::White { evaluate(board) } else { -evaluate(board) }
+}
+
+#[test]
+fn phase_follows_the_pieces_left() {
+    assert_eq!(game_phase(&Board::default()), MAX_PHASE);
+    // Kings and pawns only
+    assert_eq!(game_phase(&board("4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1")), 0);
+    // A rook and a knight each
+    assert_eq!(game_phase(&board("1n2k2r/8/8/8/8/8/8/1N2K2R w - - 0 1")), 6);
+    // Promoted queens don't go past the start
+    assert_eq!(game_phase(&board("qqqqk3/8/8/8/8/8/8/QQQQK3 w - - 0 1")), MAX_PHASE);
+}
+
+#[test]
+fn kings_belong_in_the_centre_only_in_the_endgame() {
+    let white_king = |fen: &str| white_eval(&board(fen));
+    // With the queens and rooks on, the king is safer at home
+    assert!(white_king("r2qk2r/pppppppp/8/8/8/8/PPPPPPPP/R2Q2KR w - - 0 1") > white_king("r2qk2r/pppppppp/8/8/4K3/8/PPPPPPPP/R2Q3R w - - 0 1"));
+    // With only pawns left it should come out
+    assert!(white_king("4k3/pppp4/8/8/4K3/8/4PPPP/8 w - - 0 1") > white_king("4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1"));
+}
+
+#[test]
+fn material_is_counted_in_centipawns() {
+    // A knight up, wherever it stands
+    assert!(white_eval(&board("4k3/8/8/8/8/8/8/N3K3 w - - 0 1")) > 200);
+    assert_eq!(evaluate(&Board::default()), 0);
+}
+
+#[test]
+fn flipping_the_board_negates_the_eval() {
+    let fens = [
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+        "r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R b KQkq - 0 8",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "8/pp3pk1/2p3p1/4P3/3r4/2R3P1/PP3PKP/8 w - - 0 1",
+    ];
+    for fen in fens {
+        let flipped = flip(fen);
+        assert_eq!(white_eval(&board(fen)), -white_eval(&board(&flipped)), "{} / {}", fen, flipped);
+    }
+
+    // And along random games
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..50 {
+        let mut board = Board::default();
+        for _ in 0..rng.random_range(0..80) {
+            let mut moves = Vec::new();
+            board.generate_moves(|list| {
+                moves.extend(list);
+                false
+            });
+            if moves.is_empty() {
+                break;
+            }
+            board.play(moves[rng.random_range(0..moves.len())]);
+        }
+        let flipped = Board::from_fen(&flip(&board.to_string()), false).unwrap();
+        assert_eq!(white_eval(&board), -white_eval(&flipped), "{}", board);
+    }
+}
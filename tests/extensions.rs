@@ -0,0 +1,25 @@
+mod common;
+
+use chessbot::SearchInfo;
+
+fn search(fen: &str, depth: i32) -> SearchInfo {
+    common::search(fen, depth, &[])
+}
+
+#[test]
+fn checks_are_followed_past_the_horizon() {
+    // Philidor's legacy: Nf7+ Kg8 Nh6+ Kh8 Qg8+ Rxg8 Nf7#, seven plies of checks and
+    // forced replies, seen by a six ply search
+    let info = search("r1r4k/6pp/8/3QN3/8/8/5PPP/6K1 w - - 0 1", 6);
+    assert_eq!(info.pv[0], "e5f7".parse().unwrap());
+    assert_eq!(info.mate_in(), Some(4));
+}
+
+#[test]
+fn extensions_are_bounded() {
+    // Both queens can check almost forever; the tree must still stay finite and small
+    let depth = 6;
+    let info = search("6k1/5ppp/8/3q4/3Q4/8/5PPP/6K1 w - - 0 1", depth);
+    assert!(info.pv.len() as i32 <= 2 * depth + 2, "{} plies", info.pv.len());
+    assert!(info.nodes < 2_000_000, "{} nodes", info.nodes);
+}
@@ -0,0 +1,47 @@
+use chessbot::{MoveHistory, MAX_HISTORY};
+use cozy_chess::{Board, Color, Move};
+
+fn mv(uci: &str) -> Move {
+    uci.parse().unwrap()
+}
+
+#[test]
+fn cutoffs_update_killers_history_and_countermoves() {
+    let history = MoveHistory::default();
+    let mut board = Board::default();
+    board.play(mv("e2e4"));
+
+    history.update(&board, 1, 4, mv("g8f6"), Some(mv("e2e4")), &[mv("a7a6"), mv("h7h6")]);
+
+    assert!(history.is_killer(1, mv("g8f6")));
+    assert!(!history.is_killer(2, mv("g8f6")));
+    assert!(history.history(Color::Black, mv("g8f6")) > 0);
+    assert!(history.history(Color::Black, mv("a7a6")) < 0);
+    assert_eq!(history.history(Color::White, mv("g8f6")), 0);
+    assert_eq!(history.countermove(&board, Some(mv("e2e4"))), Some(mv("g8f6")));
+
+    // Two killer slots, the newest first
+    history.update(&board, 1, 4, mv("b8c6"), None, &[]);
+    history.update(&board, 1, 4, mv("d7d5"), None, &[]);
+    assert!(history.is_killer(1, mv("d7d5")));
+    assert!(history.is_killer(1, mv("b8c6")));
+    assert!(!history.is_killer(1, mv("g8f6")));
+}
+
+#[test]
+fn history_is_bounded_and_ages() {
+    let history = MoveHistory::default();
+    let board = Board::default();
+    for _ in 0..10_000 {
+        history.update(&board, 0, 20, mv("g1f3"), None, &[]);
+    }
+    let score = history.history(Color::White, mv("g1f3"));
+    assert!(score > 0 && score <= MAX_HISTORY);
+
+    history.new_search();
+    assert_eq!(history.history(Color::White, mv("g1f3")), score / 2);
+    assert!(!history.is_killer(0, mv("g1f3")));
+
+    history.clear();
+    assert_eq!(history.history(Color::White, mv("g1f3")), 0);
+}
@@ -0,0 +1,44 @@
+use chessbot::king_safety;
+use cozy_chess::Board;
+
+fn safety(fen: &str) -> i32 {
+    king_safety(&Board::from_fen(fen, false).unwrap())
+}
+
+#[test]
+fn pawns_in_front_shelter_the_king() {
+    let sheltered = safety("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1");
+    // The same king with its pawns pushed away
+    let pushed = safety("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P1PP/2N2NP1/PPPP4/R1BQ1RK1 w - - 0 1");
+    assert!(sheltered > pushed, "{} vs {}", sheltered, pushed);
+
+    // No pawn at all left on the king's files
+    let open = safety("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP4/R1BQ1RK1 w - - 0 1");
+    assert!(pushed > open, "{} vs {}", pushed, open);
+}
+
+#[test]
+fn pawn_storms_count() {
+    // Black castled long, so its h-pawn is free to go for White's king
+    let quiet = safety("1k6/ppp5/7p/8/8/8/5PPP/6K1 w - - 0 1");
+    let stormed = safety("1k6/ppp5/8/8/7p/8/5PPP/6K1 w - - 0 1");
+    assert!(stormed < quiet, "{} vs {}", stormed, quiet);
+}
+
+#[test]
+fn attackers_on_the_king_ring_add_up() {
+    // Only the queen looks at the castled king
+    let one = safety("r1b2rk1/pppp1ppp/2n5/2b1p3/2B1P2q/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1");
+    // A knight on g4 joins it
+    let two = safety("r1b2rk1/pppp1ppp/2n5/2b1p3/2B1P1nq/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1");
+    assert!(two < one, "{} vs {}", two, one);
+}
+
+#[test]
+fn safe_checks_are_dangerous() {
+    // The rook can check on e1, which nothing of White's covers
+    let safe = safety("4r1k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+    // Here the rook on a1 covers it
+    let covered = safety("4r1k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+    assert!(safe < covered, "{} vs {}", safe, covered);
+}
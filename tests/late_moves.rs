@@ -0,0 +1,42 @@
+mod common;
+
+use chessbot::Engine;
+use common::search;
+
+const MIDDLEGAMES: &[&str] = &[
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R w KQkq - 0 8",
+];
+
+// Options that switch both late move reductions and late move pruning off
+const NO_LATE_MOVES: &[(&str, &str)] = &[("LmrMinDepth", "100"), ("LmpMaxDepth", "0")];
+
+#[test]
+fn late_moves_shrink_the_tree() {
+    for fen in MIDDLEGAMES {
+        let reduced = search(fen, 5, &[]);
+        let full = search(fen, 5, NO_LATE_MOVES);
+        assert!(reduced.nodes < full.nodes, "{}: {} vs {} nodes", fen, reduced.nodes, full.nodes);
+    }
+}
+
+#[test]
+fn forced_mates_survive_reductions() {
+    // A quiet first move the reductions must not lose
+    let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+    assert_eq!(search(fen, 6, &[]).score, search(fen, 6, NO_LATE_MOVES).score);
+    // Reducing everything as hard as possible still finds the back rank mate
+    let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+    let aggressive = [("LmrBase", "300"), ("LmrMinMoves", "0"), ("LmpBase", "0")];
+    assert_eq!(search(fen, 4, &aggressive).pv[0], "a1a8".parse().unwrap());
+}
+
+#[test]
+fn options_are_checked() {
+    let mut engine = Engine::default();
+    assert!(engine.apply_option("LmrBase", "50").is_ok());
+    assert!(engine.apply_option("LmpMaxDepth", "2").is_ok());
+    assert!(engine.apply_option("LmrDivisor", "fast").is_err());
+    assert_eq!(engine.options().lmr_base, 50);
+    assert_eq!(engine.options().lmp_max_depth, 2);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use chessbot::MATE;
+use cozy_chess::Move;
+
+fn search(fen: &str, depth: i32) -> (Option<Move>, Option<(i32, Option<i32>)>) {
+    search_with(fen, depth, 0)
+}
+
+fn search_with(fen: &str, depth: i32, contempt: i32) -> (Option<Move>, Option<(i32, Option<i32>)>) {
+    let engine = common::engine(fen, &[("Contempt", &contempt.to_string())]);
+    let best = common::search_depth(&engine, depth);
+    (best, engine.last_info().map(|info| (info.score, info.mate_in())))
+}
+
+#[test]
+fn mate_scores_count_the_distance() {
+    // Mate in one: the rook to the back rank
+    let (best, info) = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+    assert_eq!(best, Some("a1a8".parse().unwrap()));
+    assert_eq!(info, Some((MATE - 1, Some(1))));
+
+    // Mate in two with the rook ladder, even when searching deeper
+    let (_, info) = search("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 5);
+    assert_eq!(info, Some((MATE - 3, Some(2))));
+
+    // Getting mated in one
+    let (best, info) = search("7k/R7/8/8/8/8/8/1R4K1 b - - 1 1", 3);
+    assert_eq!(best, Some("h8g8".parse().unwrap()));
+    assert_eq!(info, Some((-MATE + 2, Some(-1))));
+}
+
+#[test]
+fn no_moves_at_the_root_is_no_move() {
+    // Stalemate
+    assert_eq!(search("7k/5K2/6P1/8/8/8/8/8 b - - 0 1", 3), (None, None));
+    // Checkmate
+    assert_eq!(search("R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1", 3), (None, None));
+}
+
+#[test]
+fn stalemate_is_a_draw() {
+    // g5g6 leaves Black without a move. Scored as a mate it would be played at once,
+    // scored as a draw White keeps the pawn instead.
+    let (best, info) = search_with("7k/5K2/8/6P1/8/8/8/8 w - - 0 1", 2, 1000);
+    assert_ne!(best, Some("g5g6".parse().unwrap()));
+    assert_eq!(info.unwrap().1, None);
+
+    // With draws welcome the stalemate is exactly a draw
+    let (best, info) = search_with("7k/5K2/8/6P1/8/8/8/8 w - - 0 1", 2, -1000);
+    assert_eq!(best, Some("g5g6".parse().unwrap()));
+    assert_eq!(info, Some((1000, None)));
+}
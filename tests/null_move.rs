@@ -0,0 +1,70 @@
+mod common;
+
+use chessbot::Position;
+use cozy_chess::{Board, Move};
+
+// Pawn endings where passing would change the result of a depth 7 search
+const PAWN_ENDINGS: &[&str] = &[
+    "8/3K4/8/8/2P1p3/p7/1k6/8 w - - 0 1",
+    "8/5p1k/8/1P6/6K1/8/8/8 w - - 0 1",
+    "6K1/6P1/3p4/7p/8/5P2/1k6/8 w - - 0 1",
+    "8/2p3p1/8/5P2/8/8/3k4/K7 w - - 0 1",
+];
+
+fn search(fen: &str, depth: i32, null_move: bool) -> (Move, i32) {
+    let info = common::search(fen, depth, &[("NullMove", &null_move.to_string())]);
+    (info.pv[0], info.score)
+}
+
+#[test]
+fn pawn_endings_are_searched_without_null_moves() {
+    for fen in PAWN_ENDINGS {
+        assert_eq!(search(fen, 7, true), search(fen, 7, false), "{}", fen);
+    }
+}
+
+#[test]
+fn zugzwang_with_pieces_is_verified() {
+    // Rf1 pins the rook, and once Black runs out of pawn moves it has to take on f1 and
+    // the game is drawn. Black could just pass instead, so without the verification
+    // search the deep null-move cutoffs miss the draw and White goes for Re8.
+    let fen = "8/8/p1p5/1p5p/1P5p/8/PPP2K1p/4R1rk w - - 0 1";
+    assert_eq!(search(fen, 12, true).0, "e1f1".parse().unwrap());
+}
+
+#[test]
+fn null_move_passes_the_turn() {
+    let mut position = Position::new(Board::default());
+    let key = position.key();
+    assert!(position.make_null_move());
+    assert!(position.last_move_was_null());
+    assert_eq!(position.last_move(), None);
+    assert_eq!(position.board().side_to_move(), cozy_chess::Color::Black);
+    position.unmake_move();
+    assert_eq!(position.key(), key);
+
+    // Not possible in check
+    let mut position = Position::new(Board::from_fen("4k3/8/8/8/8/8/8/4RK2 b - - 0 1", false).unwrap());
+    assert!(!position.make_null_move());
+    assert!(!position.last_move_was_null());
+}
+
+#[test]
+fn null_moves_end_the_repetition_window() {
+    // Knight out and back with passes in between is the start position again, but the
+    // game itself never went through those passes
+    let mut position = Position::new(Board::default());
+    position.make_move("g1f3".parse().unwrap());
+    position.make_null_move();
+    position.make_move("f3g1".parse().unwrap());
+    position.make_null_move();
+    assert_eq!(position.key(), Position::new(Board::default()).key());
+    assert!(!position.is_repetition());
+}
+
+#[test]
+fn null_move_pruning_keeps_forced_mates() {
+    // The rook ladder, found the same with and without passing
+    let fen = "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1";
+    assert_eq!(search(fen, 6, true).1, search(fen, 6, false).1);
+}
@@ -0,0 +1,63 @@
+use chessbot::{evaluate, pawn_structure, PawnStructure, PawnTable, Zobrist};
+use cozy_chess::{Board, Square};
+
+fn board(fen: &str) -> Board {
+    Board::from_fen(fen, false).unwrap()
+}
+
+// White's pawn structure score, middlegame and endgame, with no black pawns around
+fn white_pawns(pawns: &str) -> (i32, i32) {
+    let structure = pawn_structure(&board(&format!("4k3/8/8/8/8/8/{}/4K3 w - - 0 1", pawns)));
+    (structure.middlegame, structure.endgame)
+}
+
+#[test]
+fn weak_pawns_cost() {
+    let (connected, _) = white_pawns("3PP3");
+    let (isolated, _) = white_pawns("3P1P2");
+    assert!(isolated < connected);
+
+    // Doubled and isolated on top of it
+    let doubled = pawn_structure(&board("4k3/8/8/8/8/3P4/3P4/4K3 w - - 0 1"));
+    assert!(doubled.endgame < 2 * white_pawns("3P4").1);
+
+    // d3 can't be defended by the c4 and e4 pawns, and d4 is covered by the e5 pawn.
+    // With the black pawn on e6 instead d3 can still advance.
+    let backward = pawn_structure(&board("4k3/8/8/4p3/2P1P3/3P4/8/4K3 w - - 0 1"));
+    let free = pawn_structure(&board("4k3/8/4p3/8/2P1P3/3P4/8/4K3 w - - 0 1"));
+    assert!(backward.middlegame < free.middlegame);
+}
+
+#[test]
+fn passed_pawns_are_found() {
+    let structure = pawn_structure(&board("4k3/p7/8/7p/8/2P5/PP6/4K3 w - - 0 1"));
+    // a7 holds up a2 and b2, but nothing stands in front of c3 or h5
+    assert_eq!(structure.passed, Square::C3.bitboard() | Square::H5.bitboard());
+}
+
+#[test]
+fn runaway_pawns_are_worth_more() {
+    // The black king can't catch the a-pawn
+    let far = evaluate(&board("7k/8/8/P7/8/8/8/4K3 w - - 0 1"));
+    // Here it can
+    let near = evaluate(&board("1k6/8/8/P7/8/8/8/4K3 w - - 0 1"));
+    assert!(far > near + 200, "{} vs {}", far, near);
+
+    // A blocked passed pawn is worth less
+    let free = evaluate(&board("4k3/8/3P4/8/8/8/8/4K2R w - - 0 1"));
+    let blocked = evaluate(&board("4k3/3n4/3P4/8/8/8/8/4K2R w - - 0 1"));
+    assert!(free > blocked + 300);
+}
+
+#[test]
+fn pawn_table_round_trip() {
+    let table = PawnTable::new(1024);
+    let board = board("4k3/p7/8/3p4/8/2P5/PP6/4K3 w - - 0 1");
+    let key = Zobrist::new().hash_pawns(&board);
+    let structure = pawn_structure(&board);
+
+    assert_eq!(table.get(key), None);
+    table.store(key, &structure);
+    assert_eq!(table.get(key), Some(structure));
+    assert_eq!(table.get(key ^ 1), None::<PawnStructure>);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use chessbot::{SearchInfo, MATE_BOUND};
+
+// Openings, middlegames and endings, so the result doesn't hinge on one tree
+const POSITIONS: &[&str] = &[
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R w KQkq - 0 8",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 0 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "8/pp3pk1/2p3p1/4P3/3r4/2R3P1/PP3PKP/8 w - - 0 1",
+    "8/8/4kpp1/3p4/3P1PP1/4K3/8/8 w - - 0 1",
+];
+
+fn search(fen: &str, depth: i32, shallow_pruning: bool) -> SearchInfo {
+    common::search(fen, depth, &[("ShallowPruning", &shallow_pruning.to_string())])
+}
+
+#[test]
+fn shallow_pruning_shrinks_the_tree() {
+    // Any one tree can come out a little bigger, the move order shifts with what was
+    // pruned, but over the whole set there is clearly less to search
+    let pruned: u64 = POSITIONS.iter().map(|fen| search(fen, 6, true).nodes).sum();
+    let full: u64 = POSITIONS.iter().map(|fen| search(fen, 6, false).nodes).sum();
+    assert!(pruned < full, "{} vs {} nodes", pruned, full);
+}
+
+#[test]
+fn mates_are_found_while_behind() {
+    // Down three knights, the static eval would call every quiet move futile
+    let fen = "6k1/5ppp/8/7n/1n5n/8/5PPP/R5K1 w - - 0 1";
+    let info = search(fen, 4, true);
+    assert_eq!(info.pv[0], "a1a8".parse().unwrap());
+    assert!(info.score > MATE_BOUND);
+}
+
+#[test]
+fn checks_are_not_pruned() {
+    // In check at the root and a rook down, every reply must still be searched
+    let fen = "4k3/8/8/8/8/8/4r3/4K3 w - - 0 1";
+    assert_eq!(search(fen, 3, true).pv[0], "e1e2".parse().unwrap());
+}
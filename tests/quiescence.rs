@@ -0,0 +1,11 @@
+mod common;
+
+use common::search;
+
+#[test]
+fn captures_are_followed_past_the_horizon() {
+    // Qxd5 is the only capture and wins a knight at depth 1, until exd5 takes the queen
+    let info = search("4k3/8/4p3/3n4/8/8/8/3QK3 w - - 0 1", 1, &[]);
+    assert_ne!(info.pv[0], "d1d5".parse().unwrap());
+    assert!(info.qnodes > 0);
+}
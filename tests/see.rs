@@ -0,0 +1,43 @@
+use chessbot::see;
+use cozy_chess::Board;
+
+fn see_of(fen: &str, m: &str) -> i32 {
+    let board = Board::from_fen(fen, false).unwrap();
+    see(&board, m.parse().unwrap())
+}
+
+#[test]
+fn free_and_even_captures() {
+    // Undefended knight
+    assert_eq!(see_of("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 320);
+    // Pawn for pawn
+    assert_eq!(see_of("4k3/8/2p5/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 0);
+    // En passant
+    assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+}
+
+#[test]
+fn losing_captures() {
+    // Queen takes a pawn defended by a pawn
+    assert_eq!(see_of("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1", "d2d5"), -800);
+    // Rook takes a knight defended by a pawn
+    assert_eq!(see_of("4k3/8/2p5/3n4/8/8/3R4/4K3 w - - 0 1", "d2d5"), -180);
+}
+
+#[test]
+fn x_rays_join_the_exchange() {
+    // Doubled rooks win a pawn defended once by a rook
+    assert_eq!(see_of("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+    // Without the second rook it loses the exchange
+    assert_eq!(see_of("3rk3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5"), -400);
+}
+
+#[test]
+fn the_king_only_takes_last() {
+    // Kxd5 is fine here, nothing defends the pawn
+    assert_eq!(see_of("4k3/8/8/3p4/4K3/8/8/8 w - - 0 1", "e4d5"), 100);
+    // The king can't take back a knight the rook behind it defends
+    assert_eq!(see_of("8/8/4k3/3p4/8/2N5/8/3RK3 w - - 0 1", "c3d5"), 100);
+    // Without the rook it can
+    assert_eq!(see_of("8/8/4k3/3p4/8/2N5/8/4K3 w - - 0 1", "c3d5"), -220);
+}
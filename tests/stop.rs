@@ -0,0 +1,19 @@
+mod common;
+
+use chessbot::SearchLimits;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn unlimited_searches_stop_on_request() {
+    let engine = common::engine("r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R w KQkq - 0 8", &[]);
+    let (best, waited) = thread::scope(|scope| {
+        let search = scope.spawn(|| engine.search(SearchLimits::default(), None));
+        thread::sleep(Duration::from_millis(200));
+        let stopped = Instant::now();
+        engine.request_stop();
+        (search.join().unwrap(), stopped.elapsed())
+    });
+    assert!(waited < Duration::from_millis(500), "took {:?}", waited);
+    assert!(engine.board().is_legal(best.unwrap()));
+}
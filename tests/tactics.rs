@@ -0,0 +1,46 @@
+mod common;
+
+use common::{flip, flip_move};
+use cozy_chess::util::display_uci_move;
+
+// Positions with a forced win for white: (fen, depth, winning moves)
+const TACTICS: &[(&str, i32, &[&str])] = &[
+    // Back rank mate
+    ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2, &["a1a8"]),
+    // Rook skewer along the a-file
+    ("r7/8/8/8/k7/8/8/1R4K1 w - - 0 1", 3, &["b1a1"]),
+    // Two rook ladder, mate in 2
+    ("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 3, &["a2a7", "b1b7"]),
+    // The knight on e5 is simply loose
+    ("r1bqkb1r/pppp1ppp/5n2/4n3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 4", 3, &["f3e5"]),
+    // Knight fork of king and rook
+    ("r3k3/ppp2ppp/8/3N4/8/8/PPP2PPP/4K3 w - - 0 1", 4, &["d5c7"]),
+    // The knight is pinned to the king and can't get away from the pawn
+    ("4k3/ppp2ppp/3p4/4n3/8/8/PPP2PPP/4R1K1 w - - 0 1", 3, &["f2f4"]),
+    // Queen sacrifice into a smothered mate, Qg8+ Rxg8 Nf7#
+    ("5r1k/6pp/7N/3Q4/8/8/6PP/6K1 w - - 0 1", 4, &["d5g8"]),
+];
+
+fn best_move(fen: &str, depth: i32) -> String {
+    let engine = common::engine(fen, &[]);
+    let best = common::search_depth(&engine, depth).expect("no move found");
+    display_uci_move(engine.board(), best).to_string()
+}
+
+#[test]
+fn white_finds_forced_wins() {
+    for &(fen, depth, wins) in TACTICS {
+        let best = best_move(fen, depth);
+        assert!(wins.contains(&best.as_str()), "{}: played {}, expected one of {:?}", fen, best, wins);
+    }
+}
+
+#[test]
+fn black_finds_forced_wins() {
+    for &(fen, depth, wins) in TACTICS {
+        let fen = flip(fen);
+        let wins: Vec<String> = wins.iter().map(|m| flip_move(m)).collect();
+        let best = best_move(&fen, depth);
+        assert!(wins.contains(&best), "{}: played {}, expected one of {:?}", fen, best, wins);
+    }
+}
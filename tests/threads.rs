@@ -0,0 +1,52 @@
+mod common;
+
+use chessbot::{Engine, SearchInfo, SearchLimits, MAX_THREADS};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn engine(fen: &str, threads: usize) -> Engine {
+    common::engine(fen, &[("Threads", &threads.to_string())])
+}
+
+#[test]
+fn helpers_leave_the_result_to_the_main_thread() {
+    // Only the main thread reports, once per depth and in order
+    let engine = engine("r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R w KQkq - 0 8", 4);
+    let depths: Arc<Mutex<Vec<i32>>> = Arc::new(Mutex::new(Vec::new()));
+    let info = {
+        let depths = Arc::clone(&depths);
+        Arc::new(move |info: &SearchInfo| depths.lock().unwrap().push(info.depth))
+    };
+    let limits = SearchLimits {
+        depth: Some(6),
+        ..Default::default()
+    };
+    let best = engine.search(limits, Some(info)).unwrap();
+    assert!(engine.board().is_legal(best));
+    assert_eq!(*depths.lock().unwrap(), (1..=6).collect::<Vec<_>>());
+}
+
+#[test]
+fn mates_are_found_with_helpers() {
+    let engine = engine("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4);
+    common::search_depth(&engine, 5);
+    assert_eq!(engine.last_info().unwrap().mate_in(), Some(2));
+}
+
+#[test]
+fn helpers_stop_with_the_main_thread() {
+    let engine = engine("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", 4);
+    let start = Instant::now();
+    assert!(engine.search(SearchLimits::movetime(200), None).is_some());
+    assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+}
+
+#[test]
+fn thread_count_is_clamped() {
+    let mut engine = Engine::default();
+    engine.apply_option("Threads", "0").unwrap();
+    assert_eq!(engine.options().threads, 1);
+    engine.apply_option("Threads", "100000").unwrap();
+    assert_eq!(engine.options().threads, MAX_THREADS);
+    assert!(engine.apply_option("Threads", "many").is_err());
+}
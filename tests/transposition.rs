@@ -0,0 +1,75 @@
+mod common;
+
+use chessbot::{Entry, FlagType, TranspositionTable, MATE_BOUND};
+use cozy_chess::Move;
+
+const POSITIONS: &[&str] = &[
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/ppp2ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPP2PPP/R3K2R w KQkq - 0 8",
+    "7k/8/8/8/8/8/R7/1R4K1 w - - 0 1",
+    "r7/8/8/8/k7/8/8/1R4K1 w - - 0 1",
+];
+
+// Best move and score of a fixed depth search with a table of `hash_mb`. Shallow
+// pruning depends on the window, which the table narrows, so it is left out here
+fn search(fen: &str, depth: i32, hash_mb: usize) -> (Move, i32) {
+    let info = common::search(fen, depth, &[("Hash", &hash_mb.to_string()), ("ShallowPruning", "false")]);
+    (info.pv[0], info.score)
+}
+
+#[test]
+fn tt_cutoffs_do_not_change_the_result() {
+    for fen in POSITIONS {
+        let with_tt = search(fen, 4, 16);
+        let without_tt = search(fen, 4, 0);
+        assert_eq!(with_tt.1, without_tt.1, "{}: score differs", fen);
+        assert_eq!(with_tt.0, without_tt.0, "{}: best move differs", fen);
+    }
+}
+
+#[test]
+fn mate_scores_survive_the_table() {
+    let (_, with_tt) = search("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4, 16);
+    let (_, without_tt) = search("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4, 0);
+    assert!(with_tt >= MATE_BOUND);
+    assert_eq!(with_tt, without_tt);
+}
+
+#[test]
+fn entries_round_trip() {
+    let tt = TranspositionTable::new(1);
+    let best_move: Move = "e7e8q".parse().unwrap();
+    for (hash, flag) in [(1u64, FlagType::Exact), (2, FlagType::Lower), (3, FlagType::Upper)] {
+        tt.store(hash, Entry { score: -123, depth: 7, flag, best_move: Some(best_move) });
+        let entry = tt.get(hash).unwrap();
+        assert_eq!(entry.score, -123);
+        assert_eq!(entry.depth, 7);
+        assert!(entry.flag == flag);
+        assert_eq!(entry.best_move, Some(best_move));
+    }
+    assert!(tt.get(4).is_none());
+
+    // A fail-low store without a move keeps the move already known for the position
+    tt.store(1, Entry { score: 5, depth: 8, flag: FlagType::Upper, best_move: None });
+    assert_eq!(tt.get(1).unwrap().best_move, Some(best_move));
+}
+
+#[test]
+fn deep_entries_survive_shallow_stores() {
+    let tt = TranspositionTable::new(1);
+    tt.store(1, Entry { score: 40, depth: 12, flag: FlagType::Exact, best_move: None });
+    tt.store(1, Entry { score: -300, depth: 1, flag: FlagType::Lower, best_move: None });
+    assert_eq!(tt.get(1).unwrap().depth, 12);
+
+    // Nearly as deep, or exact, the new result wins
+    tt.store(1, Entry { score: 30, depth: 10, flag: FlagType::Lower, best_move: None });
+    assert_eq!(tt.get(1).unwrap().depth, 10);
+    tt.store(1, Entry { score: 20, depth: 2, flag: FlagType::Exact, best_move: None });
+    assert_eq!(tt.get(1).unwrap().depth, 2);
+
+    // So does anything once the deep entry is from an earlier search
+    tt.store(1, Entry { score: 40, depth: 12, flag: FlagType::Exact, best_move: None });
+    tt.new_search();
+    tt.store(1, Entry { score: -300, depth: 1, flag: FlagType::Upper, best_move: None });
+    assert_eq!(tt.get(1).unwrap().depth, 1);
+}
@@ -0,0 +1,94 @@
+use chessbot::{parse_setoption, Engine, EngineOptions, GoCommand, DEFAULT_MOVE_TIME};
+use cozy_chess::Board;
+
+fn tokens(line: &str) -> Vec<&str> {
+    line.split_whitespace().collect()
+}
+
+fn limits(go: &str, fen: &str) -> chessbot::SearchLimits {
+    GoCommand::parse(&tokens(go)).limits(&Board::from_fen(fen, false).unwrap(), &EngineOptions::default())
+}
+
+const WHITE_TO_MOVE: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1";
+const BLACK_TO_MOVE: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+
+#[test]
+fn position_plays_the_moves_after_it() {
+    let mut engine = Engine::default();
+    engine.set_position_uci(&tokens("startpos moves e2e4 e7e5 g1f3")).unwrap();
+    assert_eq!(engine.moves().len(), 3);
+    assert_eq!(engine.board().to_string(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+    // The FEN runs up to `moves`, and castling may come as king takes rook
+    engine.set_position_uci(&tokens("fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1h1 e8c8")).unwrap();
+    assert_eq!(engine.moves().len(), 2);
+    assert_eq!(engine.board().to_string(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+
+    engine.set_position_uci(&tokens("fen 4k3/8/8/8/8/8/8/4K3 b - - 0 1")).unwrap();
+    assert!(engine.moves().is_empty());
+    assert_eq!(engine.board().side_to_move(), cozy_chess::Color::Black);
+
+    assert!(engine.set_position_uci(&tokens("startpos e2e4")).is_err());
+    assert!(engine.set_position_uci(&tokens("fen not a fen")).is_err());
+    assert!(engine.set_position_uci(&tokens("startpos moves e2e5")).is_err());
+}
+
+#[test]
+fn setoption_names_and_values_may_have_spaces() {
+    let option = |line| parse_setoption(&tokens(line));
+    assert_eq!(option("name Hash value 32"), Ok(("Hash".to_string(), "32".to_string())));
+    assert_eq!(option("name Move Overhead value 30"), Ok(("Move Overhead".to_string(), "30".to_string())));
+    assert_eq!(option("name Book File value my book.bin"), Ok(("Book File".to_string(), "my book.bin".to_string())));
+    // Buttons have no value
+    assert_eq!(option("name Clear Hash"), Ok(("Clear Hash".to_string(), String::new())));
+
+    assert!(option("Hash value 32").is_err());
+    assert!(option("name value 32").is_err());
+}
+
+#[test]
+fn go_reads_every_token() {
+    let go = GoCommand::parse(&tokens("wtime 60000 btime 50000 winc 1000 binc 500 movestogo 12 depth 9 nodes 100000 movetime 700 infinite"));
+    let expected = GoCommand {
+        wtime: Some(60000),
+        btime: Some(50000),
+        winc: 1000,
+        binc: 500,
+        moves_to_go: Some(12),
+        depth: Some(9),
+        nodes: Some(100000),
+        move_time: Some(700),
+        infinite: true,
+    };
+    assert_eq!(go, expected);
+
+    // Unknown tokens and bad values are skipped
+    assert_eq!(GoCommand::parse(&tokens("ponder wtime soon depth 4")), GoCommand { depth: Some(4), ..Default::default() });
+}
+
+#[test]
+fn go_uses_the_clock_of_the_side_to_move() {
+    let white = limits("wtime 1000 btime 100000", WHITE_TO_MOVE).time_limit.unwrap();
+    let black = limits("wtime 1000 btime 100000", BLACK_TO_MOVE).time_limit.unwrap();
+    assert!(white < black, "{} vs {}", white, black);
+
+    // Never more than our share of what is left
+    assert!(limits("wtime 100000 movestogo 1000", WHITE_TO_MOVE).time_limit.unwrap() <= 100);
+
+    assert_eq!(limits("wtime 100000 movetime 300", WHITE_TO_MOVE).time_limit, Some(300));
+}
+
+#[test]
+fn go_without_our_clock_still_has_a_budget() {
+    // Only the opponent's clock
+    assert_eq!(limits("btime 1000", WHITE_TO_MOVE).time_limit, Some(DEFAULT_MOVE_TIME));
+    assert_eq!(limits("", WHITE_TO_MOVE).time_limit, Some(DEFAULT_MOVE_TIME));
+
+    // Unless something else ends the search, or it is only meant to end on `stop`
+    let depth = limits("btime 1000 depth 5", WHITE_TO_MOVE);
+    assert_eq!((depth.time_limit, depth.depth), (None, Some(5)));
+    let nodes = limits("nodes 5000", WHITE_TO_MOVE);
+    assert_eq!((nodes.time_limit, nodes.nodes), (None, Some(5000)));
+    let infinite = limits("btime 1000 infinite", WHITE_TO_MOVE);
+    assert_eq!((infinite.time_limit, infinite.depth, infinite.nodes), (None, None, None));
+}
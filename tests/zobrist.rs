@@ -0,0 +1,145 @@
+use chessbot::{Position, Zobrist};
+use cozy_chess::{Board, Move};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+fn random_move(board: &Board, rng: &mut StdRng) -> Option<Move> {
+    let mut moves = Vec::new();
+    board.generate_moves(|piece_moves| {
+        moves.extend(piece_moves);
+        false
+    });
+    if moves.is_empty() {
+        return None;
+    }
+    Some(moves[rng.random_range(0..moves.len())])
+}
+
+#[test]
+fn keys_are_deterministic() {
+    let board = Board::default();
+    assert_eq!(Zobrist::new().hash_position(&board), Zobrist::new().hash_position(&board));
+    assert_ne!(Zobrist::with_seed(1).hash_position(&board), Zobrist::with_seed(2).hash_position(&board));
+}
+
+#[test]
+fn colour_castling_en_passant_and_side_are_hashed() {
+    let zobrist = Zobrist::new();
+    let hash = |fen: &str| zobrist.hash_position(&Board::from_fen(fen, false).unwrap());
+
+    // Same squares, knight colour swapped
+    assert_ne!(hash("4k3/8/8/8/8/8/8/4K2N w - - 0 1"), hash("4k3/8/8/8/8/8/8/4K2n w - - 0 1"));
+    // Castling rights
+    assert_ne!(hash("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), hash("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1"));
+    assert_ne!(hash("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), hash("r3k2r/8/8/8/8/8/8/R3K2R w KQk - 0 1"));
+    // En passant that can be taken
+    assert_ne!(hash("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"), hash("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1"));
+    // Side to move
+    assert_ne!(hash("4k3/8/8/8/8/8/8/4K2N w - - 0 1"), hash("4k3/8/8/8/8/8/8/4K2N b - - 0 1"));
+    // Move counters are not part of the position
+    assert_eq!(hash("4k3/8/8/8/8/8/8/4K2N w - - 0 1"), hash("4k3/8/8/8/8/8/8/4K2N w - - 7 40"));
+}
+
+#[test]
+fn agrees_with_board_hash_over_random_games() {
+    let zobrist = Zobrist::new();
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+    // Two positions must share a key exactly when cozy-chess considers them the same
+    let mut ours_to_theirs = HashMap::new();
+    let mut theirs_to_ours = HashMap::new();
+
+    for _ in 0..200 {
+        let mut board = Board::default();
+        for _ in 0..120 {
+            let ours = zobrist.hash_position(&board);
+            let theirs = board.hash();
+            assert_eq!(*ours_to_theirs.entry(ours).or_insert(theirs), theirs, "{}", board);
+            assert_eq!(*theirs_to_ours.entry(theirs).or_insert(ours), ours, "{}", board);
+
+            let Some(m) = random_move(&board, &mut rng) else {
+                break;
+            };
+            board.play_unchecked(m);
+        }
+    }
+}
+
+#[test]
+fn transpositions_share_a_key() {
+    let zobrist = Zobrist::new();
+    let play = |moves: &[&str]| {
+        let mut board = Board::default();
+        for m in moves {
+            board.play(m.parse().unwrap());
+        }
+        zobrist.hash_position(&board)
+    };
+    assert_eq!(play(&["g1f3", "g8f6", "b1c3"]), play(&["b1c3", "g8f6", "g1f3"]));
+    // Knights out and back loses nothing but the move counters
+    assert_eq!(play(&["g1f3", "g8f6", "f3g1", "f6g8"]), play(&[]));
+}
+
+fn assert_keys_match(position: &Position) {
+    let zobrist = Zobrist::new();
+    let board = position.board();
+    assert_eq!(position.key(), zobrist.hash_position(board), "{}", board);
+    assert_eq!(position.pawn_key(), zobrist.hash_pawns(board), "{}", board);
+    assert_eq!(position.material_key(), zobrist.hash_material(board), "{}", board);
+}
+
+#[test]
+fn incremental_keys_match_recomputation() {
+    let mut rng = StdRng::seed_from_u64(0x0add);
+    for _ in 0..200 {
+        let mut position = Position::new(Board::default());
+        let mut played = Vec::new();
+        for _ in 0..150 {
+            let Some(m) = random_move(position.board(), &mut rng) else {
+                break;
+            };
+            played.push((position.board().clone(), position.key()));
+            position.make_move(m);
+            assert_keys_match(&position);
+        }
+        // Unmaking walks back through exactly the same positions
+        while let Some((board, key)) = played.pop() {
+            position.unmake_move();
+            assert_eq!(position.board(), &board);
+            assert_eq!(position.key(), key);
+        }
+    }
+}
+
+#[test]
+fn special_moves_update_keys() {
+    let cases = [
+        // Castling both ways, encoded king takes rook
+        ("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1", "e1h1"),
+        ("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b KQkq - 0 1", "e8a8"),
+        // En passant
+        ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"),
+        // Promotion with and without a capture
+        ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"),
+        ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8n"),
+        // Capturing a rook takes away its castling right
+        ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "a1a8"),
+    ];
+    for (fen, m) in cases {
+        let mut position = Position::new(Board::from_fen(fen, false).unwrap());
+        position.make_move(m.parse().unwrap());
+        assert_keys_match(&position);
+    }
+}
+
+#[test]
+fn pawn_and_material_keys_ignore_other_changes() {
+    let mut position = Position::new(Board::default());
+    let (pawns, material) = (position.pawn_key(), position.material_key());
+    position.make_move("g1f3".parse().unwrap());
+    assert_eq!(position.pawn_key(), pawns);
+    assert_eq!(position.material_key(), material);
+    position.make_move("e7e5".parse().unwrap());
+    assert_ne!(position.pawn_key(), pawns);
+    assert_eq!(position.material_key(), material);
+}